@@ -0,0 +1,216 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::json_file::{self, JsonFileError};
+
+mod test;
+
+pub const ACCOUNTS_FILE: &str = "accounts.json";
+// usernames end up in URLs and logs, keep them short and plain
+const USERNAME_MAX_LEN: usize = 20;
+const PASSWORD_MIN_LEN: usize = 8;
+// sessions live in memory, a restart logs everyone out anyway
+const SESSION_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+// every hash takes a core and a chunk of memory, only this many run at once
+pub const HASHING_SLOTS: usize = 4;
+// registering and logging in, per username, to slow down password guessing
+const ATTEMPTS_PER_WINDOW: usize = 10;
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum AccountsError {
+    #[error("accounts file {0}")]
+    File(#[from] JsonFileError),
+    #[error("password hashing failed: {0}")]
+    Hash(argon2::password_hash::Error),
+    #[error("usernames are 1 to {USERNAME_MAX_LEN} letters, digits, - or _")]
+    InvalidUsername,
+    #[error("passwords need at least {PASSWORD_MIN_LEN} characters")]
+    PasswordTooShort,
+    #[error("that username is taken")]
+    UsernameTaken,
+    // the same for unknown names, so they can't be fished for
+    #[error("wrong username or password")]
+    WrongPassword,
+    #[error("too many attempts, try again in a minute")]
+    TooManyAttempts,
+}
+
+impl AccountsError {
+    // the player's mistake rather than the server's
+    pub fn is_rejection(&self) -> bool {
+        !matches!(self, Self::File(_) | Self::Hash(_))
+    }
+}
+
+// body of /api/register and /api/login
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// ?token= on /play and when creating a tournament, browsers can't set
+// headers on websockets
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    pub token: Option<String>,
+}
+
+#[derive(Debug)]
+struct Session {
+    username: String,
+    expires: Instant,
+}
+
+// password hashes by username, kept in a JSON file between runs
+#[derive(Debug)]
+pub struct Accounts {
+    path: PathBuf,
+    users: Mutex<HashMap<String, String>>,
+    // by token
+    sessions: Mutex<HashMap<String, Session>>,
+    session_lifetime: Duration,
+    // unknown usernames are checked against this, so they take as long to
+    // turn away as a wrong password
+    dummy_hash: String,
+}
+
+impl Accounts {
+    // a missing file just means nobody has registered yet
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, AccountsError> {
+        let path = path.into();
+        let users = json_file::load(&path)?;
+        Ok(Self {
+            path,
+            users: Mutex::new(users),
+            sessions: Mutex::new(HashMap::new()),
+            session_lifetime: SESSION_LIFETIME,
+            dummy_hash: hash_password(&format!("{:032x}", rand::random::<u128>()))?,
+        })
+    }
+
+    #[allow(unused)] // used by tests
+    pub fn with_session_lifetime(mut self, lifetime: Duration) -> Self {
+        self.session_lifetime = lifetime;
+        self
+    }
+
+    // hashing is slow on purpose, call these off the async workers
+    pub fn register(&self, creds: &Credentials) -> Result<String, AccountsError> {
+        if !is_valid_username(&creds.username) {
+            return Err(AccountsError::InvalidUsername);
+        }
+        if creds.password.chars().count() < PASSWORD_MIN_LEN {
+            return Err(AccountsError::PasswordTooShort);
+        }
+        if self.users.lock().unwrap().contains_key(&creds.username) {
+            return Err(AccountsError::UsernameTaken);
+        }
+
+        let hash = hash_password(&creds.password)?;
+        {
+            let mut users = self.users.lock().unwrap();
+            // someone else may have taken it while we were hashing
+            if users.contains_key(&creds.username) {
+                return Err(AccountsError::UsernameTaken);
+            }
+            users.insert(creds.username.clone(), hash);
+            // an account that wasn't saved would be gone after a restart
+            if let Err(e) = json_file::save(&self.path, &*users) {
+                users.remove(&creds.username);
+                return Err(e.into());
+            }
+        }
+        println!("[Accounts] Registered \"{}\"", creds.username);
+        Ok(self.start_session(&creds.username))
+    }
+
+    pub fn login(&self, creds: &Credentials) -> Result<String, AccountsError> {
+        let hash = self.users.lock().unwrap().get(&creds.username).cloned();
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| self.dummy_hash.clone());
+        let hash = PasswordHash::new(&hash).map_err(AccountsError::Hash)?;
+        let matches = Argon2::default()
+            .verify_password(creds.password.as_bytes(), &hash)
+            .is_ok();
+        if !(known && matches) {
+            return Err(AccountsError::WrongPassword);
+        }
+        Ok(self.start_session(&creds.username))
+    }
+
+    // the username a token was issued to, while it's still good
+    pub fn session(&self, token: &str) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(token)?;
+        if session.expires <= Instant::now() {
+            sessions.remove(token);
+            return None;
+        }
+        Some(session.username.clone())
+    }
+
+    fn start_session(&self, username: &str) -> String {
+        let token = format!("{:032x}", rand::random::<u128>());
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(
+            token.clone(),
+            Session {
+                username: username.to_string(),
+                expires: now + self.session_lifetime,
+            },
+        );
+        token
+    }
+}
+
+// recent attempts to register or log in, by username
+#[derive(Debug, Default)]
+pub struct Attempts {
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Attempts {
+    // counts the attempt, false once there have been too many lately
+    pub fn allow(&self, username: &str) -> bool {
+        let mut recent = self.recent.lock().unwrap();
+        let now = Instant::now();
+        // forget about names that have gone quiet
+        recent.retain(|_, times| {
+            while times.front().is_some_and(|&t| now - t >= ATTEMPT_WINDOW) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = recent.entry(username.to_string()).or_default();
+        if times.len() >= ATTEMPTS_PER_WINDOW {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+// the names accounts can be registered under
+pub fn is_valid_username(username: &str) -> bool {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    !username.is_empty() && username.len() <= USERNAME_MAX_LEN && username.chars().all(valid_char)
+}
+
+fn hash_password(password: &str) -> Result<String, AccountsError> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(AccountsError::Hash)?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(AccountsError::Hash)?;
+    Ok(hash.to_string())
+}
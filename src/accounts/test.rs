@@ -0,0 +1,97 @@
+#[allow(unused_imports)]
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use crate::accounts::{Accounts, AccountsError, Attempts, Credentials};
+#[allow(unused_imports)]
+use crate::json_file::TempFile;
+
+#[allow(unused)] // used by tests
+fn creds(username: &str, password: &str) -> Credentials {
+    Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    }
+}
+
+#[test]
+fn test_register_rejections() {
+    let file = TempFile::new("accounts.json");
+    let accounts = Accounts::load(&file.0).unwrap();
+    for username in ["", "has space", "slash/", "émile", &"a".repeat(21)] {
+        assert!(matches!(
+            accounts.register(&creds(username, "password1")),
+            Err(AccountsError::InvalidUsername)
+        ));
+    }
+    assert!(matches!(
+        accounts.register(&creds("alice", "short")),
+        Err(AccountsError::PasswordTooShort)
+    ));
+
+    accounts.register(&creds("alice", "password1")).unwrap();
+    let taken = accounts.register(&creds("alice", "password2")).unwrap_err();
+    assert!(matches!(taken, AccountsError::UsernameTaken));
+    assert!(taken.is_rejection());
+}
+
+#[test]
+fn test_register_failed_save() {
+    let file = TempFile::new("accounts.json");
+    let accounts = Accounts::load(&file.0).unwrap();
+    // the rename fails onto a directory, so nothing is saved
+    std::fs::create_dir(&file.0).unwrap();
+    let err = accounts.register(&creds("alice", "password1")).unwrap_err();
+    assert!(matches!(err, AccountsError::File(_)));
+    assert!(!err.is_rejection());
+
+    // the name is free again once saving works
+    std::fs::remove_dir(&file.0).unwrap();
+    accounts.register(&creds("alice", "password1")).unwrap();
+}
+
+#[test]
+fn test_login() {
+    let file = TempFile::new("accounts.json");
+    let accounts = Accounts::load(&file.0).unwrap();
+    let token = accounts.register(&creds("alice", "password1")).unwrap();
+    assert_eq!(accounts.session(&token).as_deref(), Some("alice"));
+
+    // accounts outlive the server, sessions don't
+    let accounts = Accounts::load(&file.0).unwrap();
+    assert_eq!(accounts.session(&token), None);
+    let token = accounts.login(&creds("alice", "password1")).unwrap();
+    assert_eq!(accounts.session(&token).as_deref(), Some("alice"));
+
+    // unknown names look the same as wrong passwords
+    for (username, password) in [("alice", "password2"), ("bob", "password1")] {
+        assert!(matches!(
+            accounts.login(&creds(username, password)),
+            Err(AccountsError::WrongPassword)
+        ));
+    }
+    assert_eq!(accounts.session("not a token"), None);
+}
+
+#[test]
+fn test_session_expiry() {
+    let file = TempFile::new("accounts.json");
+    let accounts = Accounts::load(&file.0)
+        .unwrap()
+        .with_session_lifetime(Duration::ZERO);
+    let token = accounts.register(&creds("alice", "password1")).unwrap();
+    assert_eq!(accounts.session(&token), None);
+    let token = accounts.login(&creds("alice", "password1")).unwrap();
+    assert_eq!(accounts.session(&token), None);
+}
+
+#[test]
+fn test_attempts_limited() {
+    let attempts = Attempts::default();
+    for _ in 0..10 {
+        assert!(attempts.allow("alice"));
+    }
+    assert!(!attempts.allow("alice"));
+    // everyone else is still let through
+    assert!(attempts.allow("bob"));
+}
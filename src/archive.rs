@@ -0,0 +1,149 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::connect4::{Move, Rules};
+use crate::game::clock::TimeControl;
+
+pub const ARCHIVE_DIR: &str = "games";
+// the most games a single /api/games request gets back
+const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("game record couldn't be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("game record is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+// how a game ended, winners by username
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameResult {
+    Won { winner: String },
+    Stalemate,
+    TimedOut { winner: String },
+    Resigned { winner: String },
+    Forfeited { winner: String },
+    DrawAgreed,
+    // everyone left or the game couldn't go on
+    Abandoned,
+}
+
+// everything needed to replay a finished game
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    // unique across server restarts, unlike game ids
+    pub id: String,
+    pub red: String,
+    pub blue: String,
+    pub rules: Rules,
+    pub time_control: TimeControl,
+    pub moves: Vec<Move>,
+    // the same moves as 1-based columns, None when they include pops
+    pub notation: Option<String>,
+    pub result: GameResult,
+    // seconds since the unix epoch
+    pub started_at: u64,
+    pub ended_at: u64,
+}
+
+// ?user= filters the game list down to one player's games, ?offset= and
+// ?limit= page through it
+#[derive(Debug, Deserialize)]
+pub struct GamesQuery {
+    pub user: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+// finished games, one JSON file each
+#[derive(Clone, Debug)]
+pub struct Archive {
+    dir: PathBuf,
+}
+
+impl Archive {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn save(&self, record: &GameRecord) -> Result<(), ArchiveError> {
+        fs::create_dir_all(&self.dir)?;
+        let text = serde_json::to_string_pretty(record)?;
+        fs::write(self.dir.join(format!("{}.json", record.id)), text)?;
+        Ok(())
+    }
+
+    // newest first by when they started, blocks on the disk
+    pub fn list(&self, query: &GamesQuery) -> Result<Vec<GameRecord>, ArchiveError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        // ids start with the start time, so the names alone give the order
+        // and only the page asked for has to be read
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let key: Option<Vec<u64>> = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.split('-').map(|n| n.parse().ok()).collect());
+            if let Some(key) = key {
+                files.push((key, path));
+            }
+        }
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let user = query.user.as_deref();
+        let limit = query.limit.unwrap_or(PAGE_SIZE).min(PAGE_SIZE);
+        let records = files
+            .into_iter()
+            .filter_map(|(_, path)| match read_record(&path) {
+                Ok(record) => Some(record),
+                // one bad file shouldn't hide every other game
+                Err(e) => {
+                    println!("[Archive] Skipping \"{}\": {}", path.display(), e);
+                    None
+                }
+            })
+            .filter(|record| user.is_none_or(|user| record.red == user || record.blue == user))
+            .skip(query.offset.unwrap_or(0))
+            .take(limit)
+            .collect();
+        Ok(records)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<GameRecord>, ArchiveError> {
+        // ids are only digits and dashes, anything else could leave the dir
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-') {
+            return Ok(None);
+        }
+        match fs::read_to_string(self.dir.join(format!("{}.json", id))) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn read_record(path: &Path) -> Result<GameRecord, ArchiveError> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
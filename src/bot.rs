@@ -0,0 +1,332 @@
+use std::fmt;
+
+use rand::seq::IndexedRandom;
+use serde::Deserialize;
+
+use crate::connect4::solver::{SolveError, Solver};
+use crate::connect4::{Board, BoardState, Color, MoveKind};
+use crate::connection::{Connection, Peer};
+use crate::game::message::Message;
+
+const SEARCH_DEPTH: u32 = 6;
+// early positions can take minutes to solve, past this the perfect bot
+// plays the depth-limited search instead
+const PERFECT_NODE_LIMIT: u64 = 5_000_000;
+// every perfect bot game gets its own solver, about 2MB of table each
+// rather than the solver's default 33MB
+const PERFECT_TABLE_SIZE: usize = (1 << 16) + 1;
+
+const WIN_SCORE: i32 = 1_000_000;
+
+type Choice = (MoveKind, usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Random,
+    Greedy,
+    Search,
+    Perfect,
+}
+
+struct Bot {
+    difficulty: Difficulty,
+    board: Board,
+    color: Option<Color>,
+    solver: Option<Solver>,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Difficulty::Random => "Random",
+            Difficulty::Greedy => "Greedy",
+            Difficulty::Search => "Search",
+            Difficulty::Perfect => "Perfect",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// stands in for a player's Connection, so Game can't tell the difference
+pub fn spawn(difficulty: Difficulty) -> Connection {
+    let (conn, peer) = Connection::local(format!("{} Bot", difficulty));
+    let bot = Bot {
+        difficulty,
+        board: Board::new(),
+        color: None,
+        solver: match difficulty {
+            Difficulty::Perfect => Some(
+                Solver::with_table_size(PERFECT_TABLE_SIZE).with_node_limit(PERFECT_NODE_LIMIT),
+            ),
+            _ => None,
+        },
+    };
+    tokio::task::spawn(async move { bot.run(peer).await });
+    conn
+}
+
+impl Bot {
+    async fn run(mut self, mut peer: Peer) {
+        while let Some(msg) = peer.recv().await {
+            match msg {
+                Message::MatchMade {
+                    your_color, rules, ..
+                } => {
+                    self.color = Some(your_color);
+                    self.board = Board::with_rules(rules);
+                    continue;
+                }
+                // sent once the match starts, red moves first
+                Message::Board { .. } => {}
+                // bots play every game out
+                Message::DrawOffered { by } if Some(by) != self.color => {
+                    if peer.send(Message::DeclineDraw).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                // no takebacks either
+                Message::UndoRequested { by } if Some(by) != self.color => {
+                    if peer.send(Message::DeclineUndo).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                // always up for another game
+                Message::RematchOffered { by } if Some(by) != self.color => {
+                    if peer.send(Message::Rematch).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                // the Board snapshot that follows wakes us up
+                Message::Undone { .. } => {
+                    self.board.undo();
+                    continue;
+                }
+                Message::Moved { last_move, .. } => {
+                    // our own moves come back through here too
+                    let (color, kind, col) = (last_move.color(), last_move.kind(), last_move.col());
+                    if let Err(e) = self.board.play(color, kind, col) {
+                        eprintln!("[Bot] Lost track of the board: {}", e);
+                        return;
+                    }
+                }
+                _ => continue,
+            }
+            let Some(color) = self.color else {
+                continue;
+            };
+            if self.board.state() != BoardState::Turn(color) {
+                continue;
+            }
+
+            // searching blocks, keep it off the async workers
+            let thinking = tokio::task::spawn_blocking(move || {
+                let choice = self.choose();
+                (self, choice)
+            });
+            let (kind, column);
+            (self, (kind, column)) = match thinking.await {
+                Ok(result) => result,
+                Err(_) => return,
+            };
+            let msg = match kind {
+                MoveKind::Drop => Message::DropChip { column },
+                MoveKind::Pop => Message::PopChip { column },
+            };
+            if peer.send(msg).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn choose(&mut self) -> Choice {
+        let legal = legal_moves(&self.board);
+        let best = match self.difficulty {
+            Difficulty::Random => legal.clone(),
+            Difficulty::Greedy => greedy(&mut self.board, &legal),
+            Difficulty::Search => search(&mut self.board, &legal),
+            Difficulty::Perfect => self.perfect(&legal),
+        };
+        let mut rng = rand::rng();
+        *best.choose(&mut rng).or(legal.choose(&mut rng)).unwrap()
+    }
+
+    fn perfect(&mut self, legal: &[Choice]) -> Vec<Choice> {
+        let Some(solver) = self.solver.as_mut() else {
+            return search(&mut self.board, legal);
+        };
+        let solutions = match solver.analyze(&self.board) {
+            Ok(solutions) => solutions,
+            Err(SolveError::NodeLimit | SolveError::UnsupportedVariant) => {
+                return search(&mut self.board, legal);
+            }
+            Err(e) => {
+                eprintln!("[Bot] Solver failed: {}", e);
+                return Vec::new();
+            }
+        };
+        let Some(top) = solutions.iter().flatten().map(|s| s.score).max() else {
+            return Vec::new();
+        };
+        (0..solutions.len())
+            .filter(|&c| solutions[c].is_some_and(|s| s.score == top))
+            .map(|c| (MoveKind::Drop, c))
+            .collect()
+    }
+}
+
+// drops first, then pops when the variant allows them
+fn legal_moves(board: &Board) -> Vec<Choice> {
+    let BoardState::Turn(turn) = board.state() else {
+        return Vec::new();
+    };
+    let width = board.rules().width();
+    let drops = (0..width)
+        .filter(|&c| board.can_drop(c))
+        .map(|c| (MoveKind::Drop, c));
+    let pops = (0..width)
+        .filter(|&c| board.can_pop(turn, c))
+        .map(|c| (MoveKind::Pop, c));
+    drops.chain(pops).collect()
+}
+
+// the search plays every move on the same board and takes it back after,
+// cloning a board per node would copy its history and seen positions too
+
+// wins if it can, otherwise avoids handing the opponent a win
+fn greedy(board: &mut Board, legal: &[Choice]) -> Vec<Choice> {
+    let BoardState::Turn(turn) = board.state() else {
+        return Vec::new();
+    };
+    let mut safe = Vec::new();
+    for &(kind, col) in legal {
+        let Ok(drop) = board.play(turn, kind, col) else {
+            continue;
+        };
+        let won = drop.state == BoardState::Won(turn);
+        let hands_over_win = !won && can_win_now(board);
+        board.undo();
+        if won {
+            return vec![(kind, col)];
+        }
+        if !hands_over_win {
+            safe.push((kind, col));
+        }
+    }
+    safe
+}
+
+fn can_win_now(board: &mut Board) -> bool {
+    let BoardState::Turn(turn) = board.state() else {
+        return false;
+    };
+    legal_moves(board).into_iter().any(|(kind, col)| {
+        let Ok(drop) = board.play(turn, kind, col) else {
+            return false;
+        };
+        board.undo();
+        drop.state == BoardState::Won(turn)
+    })
+}
+
+fn search(board: &mut Board, legal: &[Choice]) -> Vec<Choice> {
+    let BoardState::Turn(turn) = board.state() else {
+        return Vec::new();
+    };
+    let mut best = Vec::new();
+    let mut top = i32::MIN;
+    for &(kind, col) in legal {
+        if board.play(turn, kind, col).is_err() {
+            continue;
+        }
+        let to_move = turn.toggle();
+        let score = -negamax(
+            board,
+            to_move,
+            SEARCH_DEPTH - 1,
+            -WIN_SCORE * 2,
+            WIN_SCORE * 2,
+        );
+        board.undo();
+        if score > top {
+            top = score;
+            best.clear();
+        }
+        if score == top {
+            best.push((kind, col));
+        }
+    }
+    best
+}
+
+// scored for the player to move
+fn negamax(board: &mut Board, to_move: Color, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    let turn = match board.state() {
+        BoardState::Turn(turn) => turn,
+        // the sooner a win comes the better, a pop can even win the game for
+        // the player who didn't make it
+        BoardState::Won(winner) => {
+            let score = WIN_SCORE + depth as i32;
+            return if winner == to_move { score } else { -score };
+        }
+        BoardState::Stalemate => return 0,
+    };
+    if depth == 0 {
+        return evaluate(board, turn);
+    }
+    let mut top = -WIN_SCORE * 2;
+    for (kind, col) in legal_moves(board) {
+        if board.play(turn, kind, col).is_err() {
+            continue;
+        }
+        let score = -negamax(board, turn.toggle(), depth - 1, -beta, -alpha);
+        board.undo();
+        top = top.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    top
+}
+
+// counts the lines each player could still complete
+fn evaluate(board: &Board, turn: Color) -> i32 {
+    let rules = board.rules();
+    let (width, height, connect) = (
+        rules.width() as i32,
+        rules.height() as i32,
+        rules.connect() as i32,
+    );
+    let layout = board.layout();
+    let mut score = 0;
+    for col in 0..width {
+        for row in 0..height {
+            for (dc, dr) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                let end_col = col + (connect - 1) * dc;
+                let end_row = row + (connect - 1) * dr;
+                if end_col >= width || end_row < 0 || end_row >= height {
+                    continue;
+                }
+                let (mut ours, mut theirs) = (0, 0);
+                for i in 0..connect {
+                    match layout[(col + i * dc) as usize][(row + i * dr) as usize] {
+                        Some(chip) if chip == turn => ours += 1,
+                        Some(_) => theirs += 1,
+                        None => {}
+                    }
+                }
+                // a line closer to completion is worth a lot more
+                if theirs == 0 && ours > 0 {
+                    score += 1 << (2 * (ours - 1));
+                } else if ours == 0 && theirs > 0 {
+                    score -= 1 << (2 * (theirs - 1));
+                }
+            }
+        }
+    }
+    score
+}
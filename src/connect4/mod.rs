@@ -1,17 +1,57 @@
 use serde::Deserialize;
 use serde::Serialize;
-use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use thiserror::Error;
 
+use crate::game::clock::TimeLeft;
 use crate::game::message::Message;
 
+pub mod solver;
 mod test;
 
+// the standard game, used unless other rules are picked
 pub const WIDTH: usize = 7;
 pub const HEIGHT: usize = 6;
+pub const CONNECT: usize = 4;
+// no side can be longer than this, whatever else fits in a bitboard
+const MAX_SIDE: usize = 16;
+
+// PopOut games are drawn once the same position comes up this many times
+const REPETITION_LIMIT: u32 = 3;
+
+pub type BoardLayout = Vec<Vec<Option<Color>>>;
+
+// Chips are stored column-major, one bit per cell, with an extra sentinel
+// bit on top of every column so shifted lines never wrap into the next one.
+// For the standard board:
+//
+//  6 13 20 27 34 41 48
+//  5 12 19 26 33 40 47
+//  4 11 18 25 32 39 46
+//  3 10 17 24 31 38 45
+//  2  9 16 23 30 37 44
+//  1  8 15 22 29 36 43
+//  0  7 14 21 28 35 42
+type Bitboard = u128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+    width: usize,
+    height: usize,
+    connect: usize,
+    variant: Variant,
+}
 
-pub type BoardLayout = [[Option<Color>; HEIGHT]; WIDTH];
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    #[default]
+    Standard,
+    // players may also pop one of their own chips out of the bottom of a column
+    PopOut,
+}
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
 pub struct Turn {
@@ -19,22 +59,50 @@ pub struct Turn {
     blue: i32,
 }
 
+// a single slot on the board, row 0 is the bottom
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cell {
+    row: usize,
+    col: usize,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Move {
     color: Color,
     row: usize,
     col: usize,
+    kind: MoveKind,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MoveKind {
+    Drop,
+    Pop,
+}
+
+// columns in the order they were played, written as 1-based digits like
+// "4453", the notation most solvers and opening books use
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Moves(Vec<usize>);
+
+#[derive(Clone, Debug)]
 pub struct Board {
-    chips: BoardLayout,
+    rules: Rules,
+    // one bitboard per color, indexed by Color::index
+    chips: [Bitboard; 2],
+    // every occupied cell, so adding a column's bottom bit finds its height
+    mask: Bitboard,
     moves: Turn,
     last_move: Option<Move>,
+    // every move so far, oldest first
+    history: Vec<Move>,
     state: BoardState,
+    // PopOut positions seen so far, keyed by red's chips, the mask and the
+    // player to move
+    seen: HashMap<(Bitboard, Bitboard, Color), u32>,
 }
 
-#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Color {
     #[default]
     Red,
@@ -55,45 +123,212 @@ pub struct DropResult {
 }
 
 impl Color {
-    fn toggle(&self) -> Self {
+    pub fn toggle(&self) -> Self {
         match self {
             Color::Red => Color::Blue,
             Color::Blue => Color::Red,
         }
     }
+
+    fn index(&self) -> usize {
+        match self {
+            Color::Red => 0,
+            Color::Blue => 1,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            width: WIDTH,
+            height: HEIGHT,
+            connect: CONNECT,
+            variant: Variant::Standard,
+        }
+    }
+}
+
+impl Rules {
+    pub fn new(width: usize, height: usize, connect: usize) -> Result<Rules, RulesError> {
+        if connect < 3 {
+            return Err(RulesError::ConnectTooShort);
+        }
+        if width == 0 || height == 0 || connect > width.max(height) {
+            return Err(RulesError::ConnectTooLong);
+        }
+        if width > MAX_SIDE || height > MAX_SIDE {
+            return Err(RulesError::TooLarge);
+        }
+        let bits = Bitboard::BITS as usize;
+        // every column needs its sentinel bit too
+        let cells = height.checked_add(1).and_then(|h| width.checked_mul(h));
+        if cells.is_none_or(|cells| cells > bits) {
+            return Err(RulesError::TooLarge);
+        }
+        // lines are found by shifting up to connect - 1 steps of a diagonal,
+        // height + 2 bits each, every shift has to stay inside the bitboard
+        let longest_shift = height
+            .checked_add(2)
+            .and_then(|h| h.checked_mul(connect - 1));
+        if longest_shift.is_none_or(|shift| shift >= bits) {
+            return Err(RulesError::TooLarge);
+        }
+        Ok(Rules {
+            width,
+            height,
+            connect,
+            variant: Variant::Standard,
+        })
+    }
+
+    pub fn with_variant(self, variant: Variant) -> Rules {
+        Rules { variant, ..self }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn connect(&self) -> usize {
+        self.connect
+    }
+
+    fn cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn column_bits(&self) -> usize {
+        self.height + 1
+    }
+
+    // vertical, horizontal, diagonal, diagonal negative
+    fn directions(&self) -> [usize; 4] {
+        let column_bits = self.column_bits();
+        [1, column_bits, column_bits + 1, column_bits - 1]
+    }
+
+    fn cell_mask(&self, col: usize, row: usize) -> Bitboard {
+        1 << (col * self.column_bits() + row)
+    }
+
+    fn bottom_mask(&self, col: usize) -> Bitboard {
+        self.cell_mask(col, 0)
+    }
+
+    fn top_mask(&self, col: usize) -> Bitboard {
+        self.cell_mask(col, self.height - 1)
+    }
+
+    fn column_mask(&self, col: usize) -> Bitboard {
+        ((1 << self.height) - 1) << (col * self.column_bits())
+    }
+
+    fn bottom_row(&self) -> Bitboard {
+        (0..self.width).fold(0, |row, col| row | self.bottom_mask(col))
+    }
+
+    fn board_mask(&self) -> Bitboard {
+        (0..self.width).fold(0, |board, col| board | self.column_mask(col))
+    }
+
+    fn has_line(&self, chips: Bitboard) -> bool {
+        for shift in self.directions() {
+            // each pass keeps the chips starting a run one longer
+            let mut runs = chips;
+            for _ in 1..self.connect {
+                runs &= runs >> shift;
+            }
+            if runs != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    // every cell in every line, overlapping lines only list shared cells once
+    fn line_cells(&self, chips: Bitboard) -> Vec<Cell> {
+        let mut cells: Bitboard = 0;
+        for shift in self.directions() {
+            let mut runs = chips;
+            for _ in 1..self.connect {
+                runs &= runs >> shift;
+            }
+            // walk each run back out from where it starts
+            for step in 0..self.connect {
+                cells |= runs << (shift * step);
+            }
+        }
+        let column_bits = self.column_bits();
+        let mut line = Vec::new();
+        while cells != 0 {
+            let bit = cells.trailing_zeros() as usize;
+            line.push(Cell {
+                row: bit % column_bits,
+                col: bit / column_bits,
+            });
+            cells &= cells - 1;
+        }
+        line
+    }
+}
+
+impl Move {
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    pub fn kind(&self) -> MoveKind {
+        self.kind
+    }
 }
 
 impl Message {
-    pub fn board(b: &Board) -> Self {
+    pub fn board(b: &Board, time: Option<TimeLeft>) -> Self {
         let BoardState::Turn(turn) = b.state else {
             unreachable!();
         };
         Message::Board {
-            turn: turn,
-            board: b.chips,
+            turn,
+            rules: b.rules,
+            board: b.layout(),
+            time,
+            history: b.history.clone(),
         }
     }
 
     pub fn won(b: &Board, winner: Color) -> Self {
         Message::Won {
             winner,
+            line: b.winning_line(),
             last_move: b.last_move.unwrap(),
-            board: b.chips,
+            board: b.layout(),
+            history: b.history.clone(),
         }
     }
 
     pub fn stalemate(b: &Board) -> Self {
         Message::Stalemate {
             last_move: b.last_move.unwrap(),
-            board: b.chips,
+            board: b.layout(),
+            history: b.history.clone(),
         }
     }
 
-    pub fn moved(b: &Board, last_move: Move, mover: Color) -> Self {
+    pub fn moved(b: &Board, last_move: Move, mover: Color, time: Option<TimeLeft>) -> Self {
         Message::Moved {
             last_mover: mover,
-            last_move: last_move,
-            board: b.chips,
+            last_move,
+            board: b.layout(),
+            time,
         }
     }
 }
@@ -110,88 +345,167 @@ pub enum PlayError {
     GameOver(Color),
     #[error("game already ended in stalemate")]
     Stalemate,
+    #[error("popping chips is not allowed in this variant")]
+    PopNotAllowed,
+    #[error("bottom chip of column is not yours")]
+    NotYourChip,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Error, Deserialize, Serialize)]
+pub enum RulesError {
+    #[error("lines must be at least 3 chips long")]
+    ConnectTooShort,
+    #[error("lines don't fit on the board")]
+    ConnectTooLong,
+    #[error("board is too large")]
+    TooLarge,
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum MovesError {
+    #[error("'{char}' at position {index} is not a column")]
+    InvalidColumn { index: usize, char: char },
+    #[error("move {index} in column {col} is illegal: {source}")]
+    IllegalMove {
+        index: usize,
+        col: usize,
+        source: PlayError,
+    },
+    #[error("only boards up to 9 columns wide can be written as digits")]
+    TooWide,
+    #[error("pops can't be written as columns")]
+    HasPops,
+    #[error("board was loaded without the order of its moves")]
+    UnknownOrder,
+}
+
+// rows and columns are board coordinates, row 0 is the bottom
+#[derive(Clone, Debug, Error)]
 pub enum LoadError {
-    InvalidSize,
-    InvalidText,
-    InvalidMoves,
+    #[error("expected {expected} rows, found {found}")]
+    WrongRowCount { expected: usize, found: usize },
+    #[error("row {row} is {found} cells wide, expected {expected}")]
+    WrongRowWidth {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("'{char}' at row {row}, column {col} is not a chip")]
+    InvalidChar { row: usize, col: usize, char: char },
+    #[error("chip at row {row}, column {col} floats over an empty cell")]
+    FloatingChip { row: usize, col: usize },
+    #[error("red has {red} chips and blue has {blue}, red moves first")]
+    InvalidMoves { red: i32, blue: i32 },
+    #[error("no capital letter marks the last move")]
     NoLastMove,
-    ExtraLastMove,
+    #[error("row {row}, column {col} marks a second last move")]
+    ExtraLastMove { row: usize, col: usize },
+    #[error("last move at row {row}, column {col} was not {color:?}'s to make")]
+    LastMoveOutOfTurn {
+        row: usize,
+        col: usize,
+        color: Color,
+    },
+    #[error("last move at row {row}, column {col} has a chip on top of it")]
+    LastMoveCovered { row: usize, col: usize },
+    #[error("both players have a line")]
+    BothWon,
 }
 
 impl Board {
     pub fn new() -> Board {
-        Board {
-            chips: [[Option::None; HEIGHT]; WIDTH],
+        Board::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> Board {
+        let mut board = Board {
+            rules,
+            chips: [0; 2],
+            mask: 0,
             state: BoardState::Turn(Color::Red),
             last_move: None,
+            history: Vec::new(),
             moves: Turn::default(),
-        }
+            seen: HashMap::new(),
+        };
+        board.record_position();
+        board
     }
 
     #[allow(unused)] // used by tests
     pub fn load(layout: &str) -> Result<Board, LoadError> {
-        let mut board = Board::new();
+        Board::load_with_rules(Rules::default(), layout)
+    }
 
-        let rows = layout.split('\n');
-        if rows.count() != HEIGHT {
-            return Err(LoadError::InvalidSize);
+    #[allow(unused)] // used by tests
+    pub fn load_with_rules(rules: Rules, layout: &str) -> Result<Board, LoadError> {
+        let mut board = Board::with_rules(rules);
+
+        let rows = layout.split('\n').count();
+        if rows != rules.height {
+            return Err(LoadError::WrongRowCount {
+                expected: rules.height,
+                found: rows,
+            });
         }
 
         let mut r_moves: i32 = 0;
         let mut b_moves: i32 = 0;
         let mut last_move: Option<Move> = None;
         for (r_inv, row) in layout.split('\n').enumerate() {
-            let r = (HEIGHT - 1) - r_inv;
-            if row.len() != WIDTH {
-                return Err(LoadError::InvalidSize);
+            let r = (rules.height - 1) - r_inv;
+            let width = row.chars().count();
+            if width != rules.width {
+                return Err(LoadError::WrongRowWidth {
+                    row: r,
+                    expected: rules.width,
+                    found: width,
+                });
             }
-            for (c, color) in row.chars().into_iter().enumerate() {
-                board.chips[c][r] = match color {
-                    'r' => {
-                        r_moves += 1;
-                        Some(Color::Red)
-                    }
-                    'b' => {
-                        b_moves += 1;
-                        Some(Color::Blue)
-                    }
-                    'R' => {
-                        r_moves += 1;
-                        if last_move.is_some() {
-                            return Err(LoadError::ExtraLastMove);
-                        }
-                        last_move = Some(Move {
-                            color: Color::Red,
+            for (c, char) in row.chars().enumerate() {
+                let chip = match char {
+                    'r' | 'R' => Color::Red,
+                    'b' | 'B' => Color::Blue,
+                    '.' => continue,
+                    _ => {
+                        return Err(LoadError::InvalidChar {
                             row: r,
                             col: c,
+                            char,
                         });
-                        Some(Color::Red)
                     }
-                    'B' => {
-                        b_moves += 1;
-                        if last_move.is_some() {
-                            return Err(LoadError::ExtraLastMove);
-                        }
-                        last_move = Some(Move {
-                            color: Color::Blue,
-                            row: r,
-                            col: c,
-                        });
-                        Some(Color::Blue)
-                    }
-                    '.' => None,
-                    _ => return Err(LoadError::InvalidText),
                 };
+                match chip {
+                    Color::Red => r_moves += 1,
+                    Color::Blue => b_moves += 1,
+                }
+                if char.is_ascii_uppercase() {
+                    if last_move.is_some() {
+                        return Err(LoadError::ExtraLastMove { row: r, col: c });
+                    }
+                    last_move = Some(Move {
+                        color: chip,
+                        row: r,
+                        col: c,
+                        kind: MoveKind::Drop,
+                    });
+                }
+                board.chips[chip.index()] |= rules.cell_mask(c, r);
+                board.mask |= rules.cell_mask(c, r);
             }
         }
 
-        let move_difference = r_moves.abs_diff(b_moves);
-        if move_difference > 1 {
-            return Err(LoadError::InvalidMoves);
+        // every chip needs one under it, whatever the variant
+        for col in 0..rules.width {
+            for row in 1..rules.height {
+                let floating = board.mask & rules.cell_mask(col, row) != 0
+                    && board.mask & rules.cell_mask(col, row - 1) == 0;
+                if floating {
+                    return Err(LoadError::FloatingChip { row, col });
+                }
+            }
         }
+
         board.moves = Turn {
             red: r_moves,
             blue: b_moves,
@@ -202,20 +516,198 @@ impl Board {
             None => return Err(LoadError::NoLastMove),
         };
 
+        // pops take chips back out and slide columns, so only standard
+        // games say anything about the order chips went in
+        if rules.variant == Variant::Standard {
+            board.check_move_order(last_move)?;
+        }
+
         board.last_move = Some(last_move);
         board.state = BoardState::Turn(last_move.color);
         let win = board.compute_win(last_move);
         board.state = board.compute_state(win);
+        board.seen.clear();
+        board.record_position();
+
+        Ok(board)
+    }
+
+    fn check_move_order(&self, last_move: Move) -> Result<(), LoadError> {
+        let (red, blue) = (self.moves.red, self.moves.blue);
+        if red != blue && red != blue + 1 {
+            return Err(LoadError::InvalidMoves { red, blue });
+        }
+        let last_mover = match red == blue {
+            true => Color::Blue,
+            false => Color::Red,
+        };
+        let Move {
+            row, col, color, ..
+        } = last_move;
+        if color != last_mover {
+            return Err(LoadError::LastMoveOutOfTurn { row, col, color });
+        }
+        if row + 1 < self.rules.height && self.mask & self.rules.cell_mask(col, row + 1) != 0 {
+            return Err(LoadError::LastMoveCovered { row, col });
+        }
+        if self.rules.has_line(self.chips[Color::Red.index()])
+            && self.rules.has_line(self.chips[Color::Blue.index()])
+        {
+            return Err(LoadError::BothWon);
+        }
+        Ok(())
+    }
 
+    #[allow(unused)] // used by tests
+    pub fn from_moves(moves: &str) -> Result<Board, MovesError> {
+        Board::from_moves_with_rules(Rules::default(), moves)
+    }
+
+    // replays the columns from the start, alternating colors from red
+    #[allow(unused)] // used by tests
+    pub fn from_moves_with_rules(rules: Rules, moves: &str) -> Result<Board, MovesError> {
+        let Moves(columns) = moves.parse()?;
+        let mut board = Board::with_rules(rules);
+        let mut color = Color::Red;
+        for (index, col) in columns.into_iter().enumerate() {
+            board
+                .drop_chip(color, col)
+                .map_err(|source| MovesError::IllegalMove {
+                    index: index + 1,
+                    col: col + 1,
+                    source,
+                })?;
+            color = color.toggle();
+        }
         Ok(board)
     }
 
+    pub fn to_moves(&self) -> Result<Moves, MovesError> {
+        if self.rules.width > 9 {
+            return Err(MovesError::TooWide);
+        }
+        if self.history.iter().any(|m| m.kind == MoveKind::Pop) {
+            return Err(MovesError::HasPops);
+        }
+        // boards from a picture only know where their chips are
+        if self.history.len() as i32 != self.moves.red + self.moves.blue {
+            return Err(MovesError::UnknownOrder);
+        }
+        Ok(Moves(self.history.iter().map(|m| m.col).collect()))
+    }
+
+    // takes back the last move, None when no moves are known
+    pub fn undo(&mut self) -> Option<Move> {
+        let last_move = self.history.pop()?;
+        self.forget_position(last_move.color.toggle());
+
+        let (col, color) = (last_move.col, last_move.color);
+        match last_move.kind {
+            MoveKind::Drop => {
+                let cell = self.rules.cell_mask(col, last_move.row);
+                self.chips[color.index()] &= !cell;
+                self.mask &= !cell;
+                match color {
+                    Color::Red => self.moves.red -= 1,
+                    Color::Blue => self.moves.blue -= 1,
+                }
+            }
+            MoveKind::Pop => {
+                // the column slides back up over the popped chip
+                let column = self.rules.column_mask(col);
+                let bottom = self.rules.bottom_mask(col);
+                let lift = |bits: Bitboard| (bits & !column) | ((bits & column) << 1);
+                self.chips = self.chips.map(lift);
+                self.mask = lift(self.mask) | bottom;
+                self.chips[color.index()] |= bottom;
+                match color {
+                    Color::Red => self.moves.red += 1,
+                    Color::Blue => self.moves.blue += 1,
+                }
+            }
+        }
+
+        self.last_move = self.history.last().copied();
+        self.state = BoardState::Turn(color);
+        Some(last_move)
+    }
+
+    pub fn play(
+        &mut self,
+        chip: Color,
+        kind: MoveKind,
+        col: usize,
+    ) -> Result<DropResult, PlayError> {
+        match kind {
+            MoveKind::Drop => self.drop_chip(chip, col),
+            MoveKind::Pop => self.pop_chip(chip, col),
+        }
+    }
+
     pub fn drop_chip(&mut self, chip: Color, col: usize) -> Result<DropResult, PlayError> {
-        match col {
-            0..WIDTH => {}
-            _ => return Err(PlayError::OutOfRange),
+        let current_turn = self.check_turn(chip, col)?;
+
+        if self.mask & self.rules.top_mask(col) != 0 {
+            return Err(PlayError::ChipOverflow);
+        }
+
+        // the carry out of the column's filled cells lands on the first empty one
+        let cell = (self.mask + self.rules.bottom_mask(col)) & self.rules.column_mask(col);
+        self.mask |= cell;
+        self.chips[current_turn.index()] |= cell;
+        let current_move = Move {
+            color: current_turn,
+            col,
+            row: cell.trailing_zeros() as usize - col * self.rules.column_bits(),
+            kind: MoveKind::Drop,
         };
 
+        match current_turn {
+            Color::Red => self.moves.red += 1,
+            Color::Blue => self.moves.blue += 1,
+        }
+
+        Ok(self.finish_move(current_move))
+    }
+
+    pub fn pop_chip(&mut self, chip: Color, col: usize) -> Result<DropResult, PlayError> {
+        let current_turn = self.check_turn(chip, col)?;
+
+        if self.rules.variant != Variant::PopOut {
+            return Err(PlayError::PopNotAllowed);
+        }
+
+        let bottom = self.rules.bottom_mask(col);
+        if self.chips[current_turn.index()] & bottom == 0 {
+            return Err(PlayError::NotYourChip);
+        }
+
+        // everything above the popped chip slides down a row
+        let column = self.rules.column_mask(col);
+        let slide = |bits: Bitboard| (bits & !column) | ((bits & column & !bottom) >> 1);
+        self.chips = self.chips.map(slide);
+        self.mask = slide(self.mask);
+        let current_move = Move {
+            color: current_turn,
+            col,
+            row: 0,
+            kind: MoveKind::Pop,
+        };
+
+        // moves counts the chips on the board, popping takes one back
+        match current_turn {
+            Color::Red => self.moves.red -= 1,
+            Color::Blue => self.moves.blue -= 1,
+        }
+
+        Ok(self.finish_move(current_move))
+    }
+
+    fn check_turn(&self, chip: Color, col: usize) -> Result<Color, PlayError> {
+        if col >= self.rules.width {
+            return Err(PlayError::OutOfRange);
+        }
+
         let current_turn = match self.state {
             BoardState::Turn(c) => c,
             BoardState::Won(winner) => {
@@ -229,143 +721,149 @@ impl Board {
         if chip.ne(&current_turn) {
             return Err(PlayError::WrongColorChip);
         }
+        Ok(current_turn)
+    }
 
-        let mut current_move: Option<Move> = None;
-        for row in 0..HEIGHT {
-            match self.chips[col][row] {
-                None => {
-                    self.chips[col][row] = Some(current_turn);
-                    current_move = Some(Move {
-                        color: current_turn,
-                        col: col,
-                        row: row,
-                    });
-                    break;
-                }
-                Some(_) => {}
-            };
-        }
-
-        let Some(current_move) = current_move else {
-            return Err(PlayError::ChipOverflow);
-        };
-
-        match current_turn {
-            Color::Red => self.moves.red += 1,
-            Color::Blue => self.moves.blue += 1,
-        }
-
+    fn finish_move(&mut self, current_move: Move) -> DropResult {
         let win = self.compute_win(current_move);
         self.state = self.compute_state(win);
         self.last_move = Some(current_move);
+        self.history.push(current_move);
+        if self.record_position() >= REPETITION_LIMIT {
+            self.state = BoardState::Stalemate;
+        }
 
-        Ok(DropResult {
+        DropResult {
             last_move: current_move,
             state: self.state,
-        })
+        }
     }
 
-    fn compute_state(&self, win: Option<Color>) -> BoardState {
-        let board_full = self.moves.red + self.moves.blue >= (WIDTH * HEIGHT) as i32;
-        match win {
-            None => {
-                if board_full {
-                    return BoardState::Stalemate;
-                }
-                let BoardState::Turn(color) = self.state else {
-                    unreachable!();
-                };
-                BoardState::Turn(color.toggle())
+    // undoes record_position for the current position, with next to move
+    fn forget_position(&mut self, next: Color) {
+        let key = (self.chips[Color::Red.index()], self.mask, next);
+        if let Some(seen) = self.seen.get_mut(&key) {
+            *seen -= 1;
+            if *seen == 0 {
+                self.seen.remove(&key);
             }
-            Some(winner) => BoardState::Won(winner),
         }
     }
 
-    fn compute_win(&self, last_move: Move) -> Option<Color> {
-        let turn = last_move.color;
-        let col = last_move.col;
-        let row = last_move.row;
-
-        // horizontal
-        let mut current_length = 0;
-        let row_lo = row - cmp::min(4, row);
-        let row_hi = row + cmp::min(4, HEIGHT - row);
-        for r in row_lo..row_hi {
-            if count_length(&mut current_length, turn, self.chips[col][r]) {
-                return Some(turn);
-            }
+    // returns how often the position has come up, only PopOut games can
+    // ever repeat one so nothing is kept for the others
+    fn record_position(&mut self) -> u32 {
+        let BoardState::Turn(next) = self.state else {
+            return 0;
+        };
+        if self.rules.variant != Variant::PopOut {
+            return 0;
         }
+        let key = (self.chips[Color::Red.index()], self.mask, next);
+        let seen = self.seen.entry(key).or_default();
+        *seen += 1;
+        *seen
+    }
 
-        // vertical
-        let mut current_length = 0;
-        let col_lo = col - cmp::min(4, col);
-        let col_hi = col + cmp::min(4, WIDTH - col);
-        for c in col_lo..col_hi {
-            if count_length(&mut current_length, turn, self.chips[c][row]) {
-                return Some(turn);
-            }
+    pub fn state(&self) -> BoardState {
+        self.state
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    // the winner's lines, empty until someone wins
+    pub fn winning_line(&self) -> Vec<Cell> {
+        match self.state {
+            BoardState::Won(winner) => self.rules.line_cells(self.chips[winner.index()]),
+            _ => Vec::new(),
         }
+    }
 
-        // diagonal
-        let mut current_length = 0;
-        let dist = cmp::min(3, cmp::min(col, row));
-        let inv_dist = cmp::min(3, cmp::min((HEIGHT - 1) - row, (WIDTH - 1) - col));
-        let d_row = row - dist;
-        let d_col = col - dist;
-        // println!("Calculating with: [{}][{}]", col, row);
-        // println!("Row: {row_lo} {row_hi}");
-        // println!("Col: {col_lo} {col_hi}");
-        // println!("Diag Lo: {dist}+{inv_dist} [{d_col}][{d_row}]");
-
-        for d in 0..(dist + inv_dist + 1) {
-            let r = d_row + d;
-            let c = d_col + d;
-            // println!("[{c}][{r}]");
-            if count_length(&mut current_length, turn, self.chips[c][r]) {
-                return Some(turn);
-            }
+    pub fn can_drop(&self, col: usize) -> bool {
+        col < self.rules.width && self.mask & self.rules.top_mask(col) == 0
+    }
+
+    pub fn can_pop(&self, chip: Color, col: usize) -> bool {
+        self.rules.variant == Variant::PopOut
+            && col < self.rules.width
+            && self.chips[chip.index()] & self.rules.bottom_mask(col) != 0
+    }
+
+    pub fn layout(&self) -> BoardLayout {
+        (0..self.rules.width)
+            .map(|col| {
+                (0..self.rules.height)
+                    .map(|row| self.chip(col, row))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn chip(&self, col: usize, row: usize) -> Option<Color> {
+        let cell = self.rules.cell_mask(col, row);
+        if self.chips[Color::Red.index()] & cell != 0 {
+            Some(Color::Red)
+        } else if self.chips[Color::Blue.index()] & cell != 0 {
+            Some(Color::Blue)
+        } else {
+            None
         }
+    }
 
-        // diagonal negative
-        let mut current_length = 0;
-        let dist = cmp::min(3, cmp::min(col, (HEIGHT - 1) - row));
-        let inv_dist = cmp::min(3, cmp::min((WIDTH - 1) - col, row));
-        let d_row = row + dist;
-        let d_col = col - dist;
-        // println!("Diag I Lo: {dist}+{inv_dist} [{d_col}][{d_row}]");
-        for d in 0..(dist + inv_dist + 1) {
-            let r = d_row - d;
-            let c = d_col + d;
-            // println!("[{c}][{r}]");
-            if count_length(&mut current_length, turn, self.chips[c][r]) {
-                return Some(turn);
+    fn compute_state(&self, win: Option<Color>) -> BoardState {
+        let board_full = self.moves.red + self.moves.blue >= self.rules.cells() as i32;
+        match win {
+            None => {
+                let BoardState::Turn(color) = self.state else {
+                    unreachable!();
+                };
+                let next = color.toggle();
+                // in PopOut a full board only ends the game when the next
+                // player has nothing to pop either
+                let can_pop = self.chips[next.index()] & self.rules.bottom_row() != 0;
+                if board_full && (self.rules.variant == Variant::Standard || !can_pop) {
+                    return BoardState::Stalemate;
+                }
+                BoardState::Turn(next)
             }
+            Some(winner) => BoardState::Won(winner),
         }
-
-        None
     }
-}
 
-fn count_length(current_length: &mut i32, turn: Color, chip: Option<Color>) -> bool {
-    if let Some(color) = chip {
-        if color == turn {
-            *current_length += 1;
-            return *current_length >= 4;
+    fn compute_win(&self, last_move: Move) -> Option<Color> {
+        let turn = last_move.color;
+        let other = turn.toggle();
+        // a pop can complete lines for both players at once, the popper wins
+        if self.rules.has_line(self.chips[turn.index()]) {
+            Some(turn)
+        } else if self.rules.has_line(self.chips[other.index()]) {
+            Some(other)
+        } else {
+            None
         }
     }
-    *current_length = 0;
-    false
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut output = String::new();
-        output.push_str("Connect4\n");
-        output.push_str("+━━━━━━━━━━━━━━━+\n");
-        for row in (0..HEIGHT).rev() {
+        let border = format!("+{}+\n", "━".repeat(self.rules.width * 2 + 1));
+        output.push_str(format!("Connect{}", self.rules.connect).as_str());
+        if self.rules.variant == Variant::PopOut {
+            output.push_str(" PopOut");
+        }
+        output.push('\n');
+        output.push_str(border.as_str());
+        for row in (0..self.rules.height).rev() {
             output.push_str("| ");
-            for col in 0..WIDTH {
-                let slot = self.chips[col][row];
+            for col in 0..self.rules.width {
+                let slot = self.chip(col, row);
                 match slot {
                     None => output.push_str("- "),
                     Some(chip) => match chip {
@@ -376,7 +874,7 @@ impl fmt::Display for Board {
             }
             output.push_str("|\n");
         }
-        output.push_str("+━━━━━━━━━━━━━━━+\n");
+        output.push_str(border.as_str());
         match self.state {
             BoardState::Turn(current) => {
                 let turn_message = format!("Turn: {:?}", current);
@@ -393,3 +891,38 @@ impl fmt::Display for Board {
         write!(f, "{}", output)
     }
 }
+
+impl Moves {
+    // 0-based, like every other column in the engine
+    #[allow(unused)] // used by tests
+    pub fn columns(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl FromStr for Moves {
+    type Err = MovesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .enumerate()
+            .map(|(index, char)| match char.to_digit(10) {
+                Some(col @ 1..=9) => Ok(col as usize - 1),
+                _ => Err(MovesError::InvalidColumn {
+                    index: index + 1,
+                    char,
+                }),
+            })
+            .collect::<Result<_, _>>()
+            .map(Moves)
+    }
+}
+
+impl fmt::Display for Moves {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for col in &self.0 {
+            write!(f, "{}", col + 1)?;
+        }
+        Ok(())
+    }
+}
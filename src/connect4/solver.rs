@@ -0,0 +1,422 @@
+use thiserror::Error;
+
+use crate::connect4::{Bitboard, Board, BoardState, Rules, Variant};
+
+// Scores follow the usual convention: positive when the player to move wins,
+// larger the sooner they win, 0 for a draw and negative for a loss.
+
+// a prime, so keys spread well with a plain modulo, about 33MB of entries
+const TABLE_SIZE: usize = (1 << 20) + 7;
+const EMPTY_ENTRY: i8 = i8::MIN;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Solution {
+    // from the point of view of the player to move
+    pub outcome: Outcome,
+    // plies until the game ends under perfect play
+    pub distance: usize,
+    pub score: i32,
+}
+
+#[derive(Debug, Error)]
+pub enum SolveError {
+    #[error("game is already over")]
+    GameOver,
+    #[error("column is full or outside of board")]
+    InvalidColumn,
+    #[error("search exceeded its node limit")]
+    NodeLimit,
+    #[error("only the standard variant can be solved")]
+    UnsupportedVariant,
+}
+
+// masks derived from the rules, worked out once per search
+struct Shape {
+    rules: Rules,
+    cells: i32,
+    bottom_row: Bitboard,
+    board_mask: Bitboard,
+    // center columns first, they take part in the most lines
+    order: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct Position<'a> {
+    shape: &'a Shape,
+    // chips of the player to move
+    current: Bitboard,
+    mask: Bitboard,
+    moves: i32,
+}
+
+// the solver keeps its transposition table between calls, so reuse it
+// when evaluating many positions from the same game
+pub struct Solver {
+    table: Vec<(Bitboard, i8)>,
+    // the table is only valid for a single set of rules
+    rules: Option<Rules>,
+    nodes: u64,
+    node_limit: Option<u64>,
+    stop_at: Option<u64>,
+}
+
+impl Solver {
+    #[allow(unused)] // used by tests
+    pub fn new() -> Self {
+        Self::with_table_size(TABLE_SIZE)
+    }
+
+    // a smaller table saves memory but forgets more positions, so searches
+    // take longer, primes spread keys best
+    pub fn with_table_size(size: usize) -> Self {
+        Self {
+            table: vec![(0, EMPTY_ENTRY); size.max(1)],
+            rules: None,
+            nodes: 0,
+            node_limit: None,
+            stop_at: None,
+        }
+    }
+
+    // gives up with SolveError::NodeLimit once a single call explores more
+    // than `limit` nodes, early positions can otherwise take minutes
+    pub fn with_node_limit(mut self, limit: u64) -> Self {
+        self.node_limit = Some(limit);
+        self
+    }
+
+    #[allow(unused)] // used by tests
+    pub fn solve(&mut self, board: &Board) -> Result<Solution, SolveError> {
+        let shape = Shape::new(board.rules);
+        let position = Position::from_board(&shape, board)?;
+        self.start(board.rules);
+        let score = self.solve_position(&position)?;
+        Ok(position.solution(score))
+    }
+
+    // evaluates every column for the player to move, None for full columns
+    pub fn analyze(&mut self, board: &Board) -> Result<Vec<Option<Solution>>, SolveError> {
+        let shape = Shape::new(board.rules);
+        let position = Position::from_board(&shape, board)?;
+        self.start(board.rules);
+        let mut solutions = Vec::with_capacity(board.rules.width);
+        for col in 0..board.rules.width {
+            match self.solve_column(&position, col) {
+                Ok(solution) => solutions.push(Some(solution)),
+                Err(SolveError::InvalidColumn) => solutions.push(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(solutions)
+    }
+
+    // evaluates a single column for the player to move
+    #[allow(unused)] // used by tests
+    pub fn solve_move(&mut self, board: &Board, col: usize) -> Result<Solution, SolveError> {
+        let shape = Shape::new(board.rules);
+        let position = Position::from_board(&shape, board)?;
+        self.start(board.rules);
+        self.solve_column(&position, col)
+    }
+
+    fn start(&mut self, rules: Rules) {
+        if self.rules != Some(rules) {
+            self.table.fill((0, EMPTY_ENTRY));
+            self.rules = Some(rules);
+        }
+        self.stop_at = self.node_limit.map(|limit| self.nodes + limit);
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop_at.is_some_and(|stop_at| self.nodes > stop_at)
+    }
+
+    fn solve_column(&mut self, position: &Position, col: usize) -> Result<Solution, SolveError> {
+        if col >= position.shape.rules.width || !position.can_play(col) {
+            return Err(SolveError::InvalidColumn);
+        }
+        let score = match position.is_winning_move(col) {
+            true => (position.shape.cells + 1 - position.moves) / 2,
+            false => -self.solve_position(&position.play(col))?,
+        };
+        Ok(position.solution(score))
+    }
+
+    fn solve_position(&mut self, position: &Position) -> Result<i32, SolveError> {
+        let cells = position.shape.cells;
+        if position.can_win_next() {
+            return Ok((cells + 1 - position.moves) / 2);
+        }
+        if position.moves >= cells {
+            return Ok(0);
+        }
+
+        // narrow the window with null-window searches, which prune far
+        // more than a single search over the whole score range
+        let mut min = -(cells - position.moves) / 2;
+        let mut max = (cells + 1 - position.moves) / 2;
+        while min < max {
+            let mut med = min + (max - min) / 2;
+            if med <= 0 && min / 2 < med {
+                med = min / 2;
+            } else if med >= 0 && max / 2 > med {
+                med = max / 2;
+            }
+            let score = self.negamax(position, med, med + 1);
+            if self.stopped() {
+                return Err(SolveError::NodeLimit);
+            }
+            if score <= med {
+                max = score;
+            } else {
+                min = score;
+            }
+        }
+        Ok(min)
+    }
+
+    // expects that the player to move cannot win immediately
+    fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.stopped() {
+            // the result is thrown away, just unwind
+            return 0;
+        }
+
+        let cells = position.shape.cells;
+        let next = position.non_losing_moves();
+        if next == 0 {
+            // every move hands the opponent a win
+            return -(cells - position.moves) / 2;
+        }
+        if position.moves + 2 >= cells {
+            return 0;
+        }
+
+        // the opponent cannot win on their next move
+        let min = -(cells - 2 - position.moves) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // we cannot win on our next move
+        let mut max = (cells - 1 - position.moves) / 2;
+        if let Some(upper) = self.lookup(position.key()) {
+            max = upper;
+        }
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        // try the moves creating the most threats first, center columns
+        // breaking ties
+        let rules = &position.shape.rules;
+        let mut ordered: Vec<(Bitboard, u32)> = Vec::with_capacity(rules.width);
+        for &col in &position.shape.order {
+            let play = next & rules.column_mask(col);
+            if play != 0 {
+                let score = position.move_score(play);
+                let at = ordered
+                    .iter()
+                    .position(|&(_, s)| s < score)
+                    .unwrap_or(ordered.len());
+                ordered.insert(at, (play, score));
+            }
+        }
+
+        for (play, _) in ordered {
+            let score = -self.negamax(&position.play_cell(play), -beta, -alpha);
+            if self.stopped() {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        self.store(position.key(), alpha);
+        alpha
+    }
+
+    fn lookup(&self, key: Bitboard) -> Option<i32> {
+        let (stored, value) = self.table[self.index(key)];
+        match stored == key && value != EMPTY_ENTRY {
+            true => Some(value as i32),
+            false => None,
+        }
+    }
+
+    fn store(&mut self, key: Bitboard, upper: i32) {
+        let index = self.index(key);
+        self.table[index] = (key, upper as i8);
+    }
+
+    fn index(&self, key: Bitboard) -> usize {
+        (key % self.table.len() as Bitboard) as usize
+    }
+}
+
+impl Solution {
+    fn from_score(score: i32, moves: i32, cells: i32) -> Self {
+        // a score encodes how many chips the winner still had in hand, so
+        // the chip count at the end must have the winner's parity
+        let (outcome, end) = match score {
+            0 => (Outcome::Draw, cells),
+            s if s > 0 => (Outcome::Win, with_parity(cells + 2 - 2 * s, moves + 1)),
+            s => (Outcome::Loss, with_parity(cells + 2 + 2 * s, moves)),
+        };
+        Solution {
+            outcome,
+            distance: (end - moves) as usize,
+            score,
+        }
+    }
+}
+
+fn with_parity(end: i32, parity: i32) -> i32 {
+    match (end - parity) % 2 {
+        0 => end,
+        _ => end - 1,
+    }
+}
+
+impl Shape {
+    fn new(rules: Rules) -> Self {
+        let center = rules.width / 2;
+        Self {
+            rules,
+            cells: rules.cells() as i32,
+            bottom_row: rules.bottom_row(),
+            board_mask: rules.board_mask(),
+            order: (0..rules.width)
+                .map(|i| match i % 2 {
+                    0 => center + i / 2,
+                    _ => center - i.div_ceil(2),
+                })
+                .collect(),
+        }
+    }
+
+    // empty cells that would complete a line for the given chips
+    fn winning_cells(&self, chips: Bitboard, mask: Bitboard) -> Bitboard {
+        let connect = self.rules.connect as isize;
+        let mut cells = 0;
+        for step in self.rules.directions() {
+            let step = step as isize;
+            // the empty cell can sit at any position along the line
+            for gap in 0..connect {
+                let mut line = self.board_mask;
+                for other in 0..connect {
+                    if other != gap {
+                        line &= shift(chips, (gap - other) * step);
+                    }
+                }
+                cells |= line;
+            }
+        }
+        cells & (self.board_mask ^ mask)
+    }
+}
+
+impl<'a> Position<'a> {
+    fn from_board(shape: &'a Shape, board: &Board) -> Result<Self, SolveError> {
+        if board.rules.variant != Variant::Standard {
+            return Err(SolveError::UnsupportedVariant);
+        }
+        let BoardState::Turn(turn) = board.state else {
+            return Err(SolveError::GameOver);
+        };
+        Ok(Self {
+            shape,
+            current: board.chips[turn.index()],
+            mask: board.mask,
+            moves: board.moves.red + board.moves.blue,
+        })
+    }
+
+    fn solution(&self, score: i32) -> Solution {
+        Solution::from_score(score, self.moves, self.shape.cells)
+    }
+
+    fn key(&self) -> Bitboard {
+        // unique per position since the mask tells the columns' heights
+        self.current + self.mask
+    }
+
+    fn can_play(&self, col: usize) -> bool {
+        self.mask & self.shape.rules.top_mask(col) == 0
+    }
+
+    fn play(&self, col: usize) -> Self {
+        let rules = &self.shape.rules;
+        self.play_cell((self.mask + rules.bottom_mask(col)) & rules.column_mask(col))
+    }
+
+    fn play_cell(&self, cell: Bitboard) -> Self {
+        Self {
+            shape: self.shape,
+            current: self.current ^ self.mask,
+            mask: self.mask | cell,
+            moves: self.moves + 1,
+        }
+    }
+
+    fn possible(&self) -> Bitboard {
+        (self.mask + self.shape.bottom_row) & self.shape.board_mask
+    }
+
+    fn can_win_next(&self) -> bool {
+        self.shape.winning_cells(self.current, self.mask) & self.possible() != 0
+    }
+
+    fn is_winning_move(&self, col: usize) -> bool {
+        let column = self.shape.rules.column_mask(col);
+        self.shape.winning_cells(self.current, self.mask) & self.possible() & column != 0
+    }
+
+    fn non_losing_moves(&self) -> Bitboard {
+        let mut possible = self.possible();
+        let opponent_wins = self
+            .shape
+            .winning_cells(self.current ^ self.mask, self.mask);
+        let forced = possible & opponent_wins;
+        if forced != 0 {
+            if forced & (forced - 1) != 0 {
+                // two threats at once, nothing saves us
+                return 0;
+            }
+            possible = forced;
+        }
+        // never play right under a cell the opponent needs
+        possible & !(opponent_wins >> 1)
+    }
+
+    fn move_score(&self, cell: Bitboard) -> u32 {
+        self.shape
+            .winning_cells(self.current | cell, self.mask)
+            .count_ones()
+    }
+}
+
+fn shift(bits: Bitboard, by: isize) -> Bitboard {
+    match by >= 0 {
+        true => bits << by,
+        false => bits >> -by,
+    }
+}
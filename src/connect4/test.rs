@@ -1,5 +1,13 @@
 #[allow(unused_imports)]
-use crate::connect4::{Board, BoardState, Color};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+#[allow(unused_imports)]
+use crate::connect4::solver::{Outcome, SolveError, Solver};
+#[allow(unused_imports)]
+use crate::connect4::{
+    Board, BoardState, Cell, Color, HEIGHT, LoadError, MoveKind, Moves, MovesError, PlayError,
+    Rules, RulesError, Variant, WIDTH,
+};
 
 #[test]
 fn test_win_vertical() {
@@ -38,7 +46,7 @@ r......
 bR.....
 brr....
 rbbr...
-rbbb..."#;
+rbbbr.."#;
 
     let board = Board::load(layout);
     let board = board.unwrap();
@@ -53,10 +61,682 @@ fn test_win_diagonal() {
 ......r
 .....rb
 ....rbb
-...Rrbb"#;
+r..Rrbb"#;
 
     let board = Board::load(layout);
     let board = board.unwrap();
     println!("{board}");
     assert_eq!(board.state, BoardState::Won(Color::Red));
 }
+
+#[test]
+fn test_no_win_across_columns() {
+    // the top of one column and the bottom of the next are only
+    // separated by the sentinel bit, they must not form a line
+    let layout = r#"r......
+r......
+b......
+b......
+rR.....
+brb...."#;
+
+    let board = Board::load(layout);
+    let board = board.unwrap();
+    println!("{board}");
+    assert_eq!(board.state, BoardState::Turn(Color::Blue));
+}
+
+#[test]
+fn test_drop_chip_stacks() {
+    let mut board = Board::new();
+    for (turn, color) in [Color::Red, Color::Blue, Color::Red, Color::Blue]
+        .into_iter()
+        .enumerate()
+    {
+        let drop = board.drop_chip(color, 3).unwrap();
+        assert_eq!(drop.last_move.row, turn);
+        assert_eq!(board.layout()[3][turn], Some(color));
+    }
+    assert_eq!(board.layout()[3][4], None);
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+}
+
+#[test]
+fn test_drop_chip_overflow() {
+    let mut board = Board::new();
+    let mut color = Color::Red;
+    for _ in 0..HEIGHT {
+        board.drop_chip(color, 0).unwrap();
+        color = color.toggle();
+    }
+    assert!(matches!(
+        board.drop_chip(color, 0),
+        Err(PlayError::ChipOverflow)
+    ));
+}
+
+// plays a sequence of 1-based columns, alternating colors from red
+#[allow(unused)]
+fn play(moves: &str) -> Board {
+    let mut board = Board::new();
+    let mut color = Color::Red;
+    for c in moves.chars() {
+        let col = c.to_digit(10).unwrap() as usize - 1;
+        board.drop_chip(color, col).unwrap();
+        color = color.toggle();
+    }
+    board
+}
+
+// plain negamax without any pruning, scored like the solver
+#[allow(unused)]
+fn reference_score(board: &Board) -> i32 {
+    let BoardState::Turn(turn) = board.state else {
+        unreachable!();
+    };
+    let cells = board.rules.cells() as i32;
+    let moves = board.moves.red + board.moves.blue;
+    let mut best = None;
+    for col in 0..board.rules.width {
+        let mut next = board.clone();
+        let score = match next.drop_chip(turn, col) {
+            Err(_) => continue,
+            Ok(drop) => match drop.state {
+                BoardState::Won(_) => (cells + 1 - moves) / 2,
+                BoardState::Stalemate => 0,
+                BoardState::Turn(_) => -reference_score(&next),
+            },
+        };
+        best = Some(best.map_or(score, |b: i32| b.max(score)));
+    }
+    best.unwrap()
+}
+
+#[test]
+fn test_solver_immediate_win() {
+    let board = play("121212");
+    let solution = Solver::new().solve(&board).unwrap();
+    assert_eq!(solution.outcome, Outcome::Win);
+    assert_eq!(solution.distance, 1);
+}
+
+#[test]
+fn test_solver_double_threat_loss() {
+    // red threatens both ends of an open three on the bottom row
+    let board = play("33445");
+    let solution = Solver::new().solve(&board).unwrap();
+    assert_eq!(solution.outcome, Outcome::Loss);
+    assert_eq!(solution.distance, 2);
+}
+
+#[test]
+fn test_solver_solve_move() {
+    let board = play("121212");
+    let mut solver = Solver::new();
+    let win = solver.solve_move(&board, 0).unwrap();
+    assert_eq!(win.outcome, Outcome::Win);
+    assert_eq!(win.distance, 1);
+    // anything else lets blue finish their own column
+    let loss = solver.solve_move(&board, 2).unwrap();
+    assert_eq!(loss.outcome, Outcome::Loss);
+    assert_eq!(loss.distance, 2);
+}
+
+#[test]
+fn test_solver_analyze() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut solver = Solver::new();
+    for _ in 0..5 {
+        let Some(board) = random_board(&mut rng, Rules::default(), 32) else {
+            continue;
+        };
+        let best = solver.solve(&board).unwrap();
+        let solutions = solver.analyze(&board).unwrap();
+        assert_eq!(solutions.len(), WIDTH);
+        let top = solutions.iter().flatten().map(|s| s.score).max();
+        assert_eq!(top, Some(best.score), "{board}");
+    }
+}
+
+#[test]
+fn test_solver_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let mut checked = 0;
+    while checked < 10 {
+        let Some(board) = random_board(&mut rng, Rules::default(), 36) else {
+            continue;
+        };
+        let solution = Solver::new().solve(&board).unwrap();
+        assert_eq!(solution.score, reference_score(&board), "{board}");
+        checked += 1;
+    }
+}
+
+#[test]
+fn test_solver_small_table() {
+    // collisions only cost time, never correctness
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut solver = Solver::with_table_size(1009);
+    let mut checked = 0;
+    while checked < 5 {
+        let Some(board) = random_board(&mut rng, Rules::default(), 30) else {
+            continue;
+        };
+        let solution = solver.solve(&board).unwrap();
+        assert_eq!(solution.score, reference_score(&board), "{board}");
+        checked += 1;
+    }
+}
+
+// random unfinished position with the given number of chips, if the
+// random play didn't end the game first
+#[allow(unused)]
+fn random_board(rng: &mut StdRng, rules: Rules, moves: i32) -> Option<Board> {
+    let mut board = Board::with_rules(rules);
+    let mut color = Color::Red;
+    while board.moves.red + board.moves.blue < moves {
+        if board
+            .drop_chip(color, rng.random_range(0..rules.width))
+            .is_ok()
+        {
+            color = color.toggle();
+        }
+        if !matches!(board.state, BoardState::Turn(_)) {
+            return None;
+        }
+    }
+    Some(board)
+}
+
+#[test]
+fn test_solver_node_limit() {
+    let mut solver = Solver::new().with_node_limit(1000);
+    assert!(matches!(
+        solver.solve(&Board::new()),
+        Err(SolveError::NodeLimit)
+    ));
+    // a limited solver still finishes small searches
+    let solution = solver.solve(&play("121212")).unwrap();
+    assert_eq!(solution.outcome, Outcome::Win);
+}
+
+#[test]
+fn test_rules_limits() {
+    assert!(Rules::new(8, 7, 4).is_ok());
+    assert!(Rules::new(9, 7, 5).is_ok());
+    assert!(matches!(
+        Rules::new(7, 6, 2),
+        Err(RulesError::ConnectTooShort)
+    ));
+    assert!(matches!(
+        Rules::new(4, 3, 5),
+        Err(RulesError::ConnectTooLong)
+    ));
+    assert!(matches!(Rules::new(17, 7, 4), Err(RulesError::TooLarge)));
+}
+
+#[test]
+fn test_rules_reject_overflow() {
+    // sizes that overflowed or wrapped the bitboard arithmetic
+    assert!(matches!(
+        Rules::new(1 << 62, 3, 3),
+        Err(RulesError::TooLarge)
+    ));
+    assert!(matches!(
+        Rules::new(3, usize::MAX, 3),
+        Err(RulesError::TooLarge)
+    ));
+    assert!(matches!(
+        Rules::new(usize::MAX, usize::MAX, usize::MAX),
+        Err(RulesError::TooLarge)
+    ));
+    // fits in the bitboard, but a line's shifts wouldn't
+    assert!(matches!(Rules::new(2, 62, 3), Err(RulesError::TooLarge)));
+    assert!(matches!(Rules::new(7, 16, 9), Err(RulesError::TooLarge)));
+    assert!(matches!(Rules::new(4, 17, 4), Err(RulesError::TooLarge)));
+
+    // the largest boards that are allowed still find their lines
+    for (width, height, connect) in [(16, 7, 4), (7, 15, 8), (8, 14, 8)] {
+        let rules = Rules::new(width, height, connect).unwrap();
+        let mut board = Board::with_rules(rules);
+        let moves: Vec<usize> = (0..connect - 1).flat_map(|_| [0, 1]).collect();
+        for col in moves {
+            let color = match board.state {
+                BoardState::Turn(color) => color,
+                _ => unreachable!(),
+            };
+            board.play(color, MoveKind::Drop, col).unwrap();
+        }
+        board.play(Color::Red, MoveKind::Drop, 0).unwrap();
+        assert_eq!(board.state, BoardState::Won(Color::Red));
+        assert_eq!(board.winning_line().len(), connect);
+    }
+}
+
+#[test]
+fn test_win_connect_five() {
+    let rules = Rules::new(9, 7, 5).unwrap();
+    let layout = r#".........
+.........
+.........
+.........
+.........
+.bbbb....
+.rrrr...R"#;
+
+    let board = Board::load_with_rules(rules, layout).unwrap();
+    println!("{board}");
+    assert_eq!(board.state, BoardState::Turn(Color::Blue));
+
+    let layout = r#".........
+.........
+.........
+.........
+.........
+.bbbb....
+.rrrrR..."#;
+    let board = Board::load_with_rules(rules, layout);
+    let board = board.unwrap();
+    println!("{board}");
+    assert_eq!(board.state, BoardState::Won(Color::Red));
+}
+
+#[test]
+fn test_large_board_columns() {
+    // 9 columns of 7 need more than 64 bits
+    let rules = Rules::new(9, 7, 4).unwrap();
+    let mut board = Board::with_rules(rules);
+    let mut color = Color::Red;
+    for col in [8; 7] {
+        board.drop_chip(color, col).unwrap();
+        color = color.toggle();
+    }
+    assert_eq!(board.layout()[8].len(), 7);
+    assert_eq!(board.layout()[8][6], Some(Color::Red));
+    assert!(!board.can_drop(8));
+    assert_eq!(board.state, BoardState::Turn(Color::Blue));
+}
+
+#[test]
+fn test_solver_small_board_matches_reference() {
+    let rules = Rules::new(5, 4, 3).unwrap();
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut checked = 0;
+    while checked < 10 {
+        let Some(board) = random_board(&mut rng, rules, 10) else {
+            continue;
+        };
+        let solution = Solver::new().solve(&board).unwrap();
+        assert_eq!(solution.score, reference_score(&board), "{board}");
+        checked += 1;
+    }
+}
+
+#[allow(unused)]
+fn popout() -> Rules {
+    Rules::default().with_variant(Variant::PopOut)
+}
+
+#[test]
+fn test_pop_chip_slides_column() {
+    let mut board = Board::with_rules(popout());
+    board.drop_chip(Color::Red, 0).unwrap();
+    board.drop_chip(Color::Blue, 0).unwrap();
+    board.drop_chip(Color::Red, 0).unwrap();
+    assert!(matches!(
+        board.pop_chip(Color::Blue, 0),
+        Err(PlayError::NotYourChip)
+    ));
+    board.drop_chip(Color::Blue, 1).unwrap();
+
+    let pop = board.pop_chip(Color::Red, 0).unwrap();
+    println!("{board}");
+    assert_eq!(pop.state, BoardState::Turn(Color::Blue));
+    assert_eq!(pop.last_move.kind(), MoveKind::Pop);
+    assert_eq!(board.chip(0, 0), Some(Color::Blue));
+    assert_eq!(board.chip(0, 1), Some(Color::Red));
+    assert_eq!(board.chip(0, 2), None);
+    // the column has room again
+    assert_eq!(board.mask.count_ones(), 3);
+}
+
+#[test]
+fn test_pop_chip_standard_rules() {
+    let mut board = Board::new();
+    board.drop_chip(Color::Red, 3).unwrap();
+    board.drop_chip(Color::Blue, 2).unwrap();
+    assert!(matches!(
+        board.pop_chip(Color::Red, 3),
+        Err(PlayError::PopNotAllowed)
+    ));
+}
+
+#[test]
+fn test_pop_simultaneous_win() {
+    // popping red's chip lines up both rows, the popper takes the win
+    let layout = r#".......
+.......
+.......
+r......
+brrr...
+rbbB..b"#;
+
+    let mut board = Board::load_with_rules(popout(), layout).unwrap();
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+    let pop = board.pop_chip(Color::Red, 0).unwrap();
+    println!("{board}");
+    assert_eq!(pop.state, BoardState::Won(Color::Red));
+}
+
+#[test]
+fn test_pop_hands_opponent_win() {
+    let layout = r#".......
+.......
+.......
+.......
+b.....r
+rbbB.rr"#;
+
+    let mut board = Board::load_with_rules(popout(), layout).unwrap();
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+    let pop = board.pop_chip(Color::Red, 0).unwrap();
+    println!("{board}");
+    assert_eq!(pop.state, BoardState::Won(Color::Blue));
+}
+
+#[test]
+fn test_popout_repetition_draw() {
+    let mut board = Board::with_rules(popout());
+    // back to the empty board every four moves, the third time is a draw
+    for round in 0..2 {
+        board.drop_chip(Color::Red, 0).unwrap();
+        board.drop_chip(Color::Blue, 1).unwrap();
+        board.pop_chip(Color::Red, 0).unwrap();
+        let pop = board.pop_chip(Color::Blue, 1).unwrap();
+        let expected = match round {
+            0 => BoardState::Turn(Color::Red),
+            _ => BoardState::Stalemate,
+        };
+        assert_eq!(pop.state, expected);
+    }
+}
+
+#[test]
+fn test_popout_full_board_continues() {
+    let layout = r#"Bbrrbbr
+rrbbrrb
+bbrrbbr
+rrbbrrb
+bbrrbbr
+rrbbrrb"#;
+
+    let board = Board::load(layout).unwrap();
+    assert_eq!(board.state, BoardState::Stalemate);
+
+    // red can still pop one of the chips along the bottom
+    let mut board = Board::load_with_rules(popout(), layout).unwrap();
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+    board.pop_chip(Color::Red, 0).unwrap();
+    assert!(board.can_drop(0));
+}
+
+#[test]
+fn test_moves_round_trip() {
+    let board = Board::from_moves("4453").unwrap();
+    assert_eq!(board.to_moves().unwrap().to_string(), "4453");
+    assert_eq!(board.layout(), play("4453").layout());
+
+    let moves: Moves = "1234567".parse().unwrap();
+    assert_eq!(moves.columns(), &[0, 1, 2, 3, 4, 5, 6]);
+    assert_eq!(moves.to_string(), "1234567");
+}
+
+#[test]
+fn test_moves_win() {
+    let board = Board::from_moves("1212121").unwrap();
+    assert_eq!(board.state, BoardState::Won(Color::Red));
+    assert_eq!(board.to_moves().unwrap().to_string(), "1212121");
+
+    let err = Board::from_moves("12121212").unwrap_err();
+    assert!(matches!(
+        err,
+        MovesError::IllegalMove {
+            index: 8,
+            col: 2,
+            source: PlayError::GameOver(Color::Red)
+        }
+    ));
+}
+
+#[test]
+fn test_moves_invalid() {
+    assert!(matches!(
+        Board::from_moves("44x0"),
+        Err(MovesError::InvalidColumn {
+            index: 3,
+            char: 'x'
+        })
+    ));
+    assert!(matches!(
+        Board::from_moves("448"),
+        Err(MovesError::IllegalMove {
+            index: 3,
+            source: PlayError::OutOfRange,
+            ..
+        })
+    ));
+    assert!(matches!(
+        Board::from_moves("1111111"),
+        Err(MovesError::IllegalMove {
+            index: 7,
+            source: PlayError::ChipOverflow,
+            ..
+        })
+    ));
+
+    // a picture doesn't say what order the chips went in
+    let layout = r#".......
+.......
+.......
+.......
+.......
+rB....."#;
+    let board = Board::load(layout).unwrap();
+    assert!(matches!(board.to_moves(), Err(MovesError::UnknownOrder)));
+
+    let mut board = Board::from_moves_with_rules(popout(), "11").unwrap();
+    board.pop_chip(Color::Red, 0).unwrap();
+    assert!(matches!(board.to_moves(), Err(MovesError::HasPops)));
+}
+
+#[test]
+fn test_load_errors() {
+    let err = Board::load(".......\n.......").unwrap_err();
+    assert!(matches!(
+        err,
+        LoadError::WrongRowCount {
+            expected: 6,
+            found: 2
+        }
+    ));
+
+    let layout = r#".......
+.......
+.......
+.......
+.......
+rB...."#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::WrongRowWidth {
+            row: 0,
+            found: 6,
+            ..
+        })
+    ));
+
+    let layout = r#".......
+.......
+.......
+.......
+.......
+rBx...."#;
+    let err = Board::load(layout).unwrap_err();
+    assert!(matches!(
+        err,
+        LoadError::InvalidChar {
+            row: 0,
+            col: 2,
+            char: 'x'
+        }
+    ));
+    assert_eq!(err.to_string(), "'x' at row 0, column 2 is not a chip");
+}
+
+#[test]
+fn test_load_impossible_layouts() {
+    let layout = r#".......
+.......
+.......
+.......
+.B.....
+r......"#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::FloatingChip { row: 1, col: 1 })
+    ));
+
+    // blue can't have moved more often than red
+    let layout = r#".......
+.......
+.......
+.......
+.......
+rbB...."#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::InvalidMoves { red: 1, blue: 2 })
+    ));
+
+    let layout = r#".......
+.......
+.......
+.......
+.......
+rR....."#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::InvalidMoves { red: 2, blue: 0 })
+    ));
+
+    let layout = r#".......
+.......
+.......
+.......
+.......
+Rb....."#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::LastMoveOutOfTurn { row: 0, col: 0, .. })
+    ));
+
+    let layout = r#".......
+.......
+.......
+.......
+r......
+Bbr...."#;
+    assert!(matches!(
+        Board::load(layout),
+        Err(LoadError::LastMoveCovered { row: 0, col: 0 })
+    ));
+
+    let layout = r#".......
+.......
+r......
+r......
+r......
+rbbbB.."#;
+    assert!(matches!(Board::load(layout), Err(LoadError::BothWon)));
+}
+
+#[test]
+fn test_winning_line() {
+    let layout = r#".......
+.......
+.......
+.......
+...bb.b
+...rrRr"#;
+    let board = Board::load(layout).unwrap();
+    let line: Vec<Cell> = (3..7).map(|col| Cell { row: 0, col }).collect();
+    assert_eq!(board.winning_line(), line);
+
+    // one drop finishing a column and a diagonal reports both
+    let layout = r#".......
+.......
+...R...
+..rr.b.
+.rbr.b.
+rbbr.b."#;
+    let board = Board::load(layout).unwrap();
+    assert_eq!(board.state, BoardState::Won(Color::Red));
+    let line = board.winning_line();
+    assert_eq!(line.len(), 7);
+    assert!(line.contains(&Cell { row: 0, col: 0 }));
+    assert!(line.contains(&Cell { row: 3, col: 3 }));
+
+    assert!(Board::new().winning_line().is_empty());
+}
+
+#[test]
+fn test_undo_drop() {
+    let mut board = Board::from_moves("4453").unwrap();
+    let undone = board.undo().unwrap();
+    assert_eq!((undone.color, undone.col), (Color::Blue, 2));
+    assert_eq!(board.layout(), Board::from_moves("445").unwrap().layout());
+    assert_eq!(board.state, BoardState::Turn(Color::Blue));
+    assert_eq!(board.last_move.unwrap().col, 4);
+    assert_eq!(board.moves.blue, 1);
+
+    // a won game goes back to the winner's turn
+    let mut board = Board::from_moves("1212121").unwrap();
+    board.undo().unwrap();
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+    assert!(board.winning_line().is_empty());
+    board.drop_chip(Color::Red, 6).unwrap();
+
+    let mut board = Board::new();
+    assert!(board.undo().is_none());
+}
+
+#[test]
+fn test_undo_pop() {
+    let mut board = Board::from_moves_with_rules(popout(), "1122").unwrap();
+    let before = board.layout();
+    board.pop_chip(Color::Red, 0).unwrap();
+    let undone = board.undo().unwrap();
+    assert_eq!(undone.kind(), MoveKind::Pop);
+    assert_eq!(board.layout(), before);
+    assert_eq!(board.moves.red, 2);
+    assert_eq!(board.mask.count_ones(), 4);
+    assert_eq!(board.state, BoardState::Turn(Color::Red));
+}
+
+#[test]
+fn test_undo_forgets_repetition() {
+    let mut board = Board::with_rules(popout());
+    board.drop_chip(Color::Red, 0).unwrap();
+    board.drop_chip(Color::Blue, 1).unwrap();
+    board.pop_chip(Color::Red, 0).unwrap();
+    board.pop_chip(Color::Blue, 1).unwrap();
+    // taking back the second return to the start doesn't count it, so
+    // replaying it and going round again is only the third
+    board.undo().unwrap();
+    board.pop_chip(Color::Blue, 1).unwrap();
+    board.drop_chip(Color::Red, 0).unwrap();
+    board.drop_chip(Color::Blue, 1).unwrap();
+    board.pop_chip(Color::Red, 0).unwrap();
+    let pop = board.pop_chip(Color::Blue, 1).unwrap();
+    assert_eq!(pop.state, BoardState::Stalemate);
+}
@@ -4,7 +4,11 @@ use tokio::sync::{
     oneshot,
 };
 
+use crate::bot::Difficulty;
+use crate::connect4::{self, Rules, RulesError, Variant};
+use crate::game::clock::TimeControl;
 use crate::game::message::Message as GameMessage;
+use serde::Deserialize;
 use tokio_util::sync::CancellationToken;
 use warp::{ws::Message as WsMessage, ws::WebSocket};
 
@@ -15,26 +19,140 @@ pub type ConnRx = mpsc::UnboundedReceiver<ConnectionUpdate>;
 pub enum ConnectionUpdate {
     Connected(Connection),
     Disconnected(String),
+    // a read-only viewer for the game with this id
+    Watching(usize, Connection),
+    // a read-only feed of who's waiting and playing
+    Observing(Connection),
+}
+
+// what a socket is for, only players get to send anything
+#[derive(Clone, Copy, Debug)]
+enum Role {
+    Player,
+    Spectator(usize),
+    Observer,
+}
+
+// query string of /play/{username}
+#[derive(Debug, Default, Deserialize)]
+pub struct PlayOptions {
+    pub bot: Option<Difficulty>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub connect: Option<usize>,
+    pub variant: Option<Variant>,
+    // seconds
+    pub move_time: Option<u32>,
+    pub clock: Option<u32>,
+    pub increment: Option<u32>,
+    // token from MatchMade, to rejoin a game after dropping out
+    pub resume: Option<String>,
+    // "new" to open a private room, or the code of one to join
+    pub room: Option<String>,
+    // play a best-of-N series instead of a single game
+    pub best_of: Option<u32>,
+    // skip matchmaking, only play people who challenge you
+    #[serde(default)]
+    pub challenges_only: bool,
+    // play your games in this tournament, see /api/tournaments
+    pub tournament: Option<usize>,
+}
+
+impl PlayOptions {
+    // anything left out falls back to the standard game
+    pub fn rules(&self) -> Result<Rules, RulesError> {
+        Rules::new(
+            self.width.unwrap_or(connect4::WIDTH),
+            self.height.unwrap_or(connect4::HEIGHT),
+            self.connect.unwrap_or(connect4::CONNECT),
+        )
+        .map(|rules| rules.with_variant(self.variant.unwrap_or_default()))
+    }
+
+    // a limit of 0 means no limit, same as leaving it out
+    pub fn time_control(&self) -> TimeControl {
+        TimeControl {
+            move_time: self.move_time.filter(|&s| s > 0),
+            clock: self.clock.filter(|&s| s > 0),
+            increment: self.increment.unwrap_or(0),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Connection {
     pub username: String,
+    pub options: PlayOptions,
+    // set by the lobby, bots aren't rated
+    pub rating: Option<i32>,
+    local: bool,
     accept_tx: Option<oneshot::Sender<bool>>,
     close_token: CancellationToken,
     rx: mpsc::UnboundedReceiver<GameMessage>,
     tx: mpsc::UnboundedSender<GameMessage>,
 }
 
+// the other end of a Connection that isn't backed by a websocket
+#[derive(Debug)]
+pub struct Peer {
+    close_token: CancellationToken,
+    rx: mpsc::UnboundedReceiver<GameMessage>,
+    tx: mpsc::UnboundedSender<GameMessage>,
+}
+
+impl Peer {
+    // the message isn't handed back, it's large and nobody needs it
+    pub fn send(&self, m: GameMessage) -> Result<(), SendError<()>> {
+        self.tx.send(m).map_err(|_| SendError(()))
+    }
+
+    // None once the Connection is closed
+    pub async fn recv(&mut self) -> Option<GameMessage> {
+        tokio::select! {
+            m = self.rx.recv() => m,
+            _ = self.close_token.cancelled() => None,
+        }
+    }
+}
+
 impl Connection {
-    pub fn send(&self, m: GameMessage) -> Result<(), SendError<GameMessage>> {
-        self.tx.send(m)
+    pub fn local(username: String) -> (Connection, Peer) {
+        let (im_tx, im_rx) = mpsc::unbounded_channel::<GameMessage>();
+        let (og_tx, og_rx) = mpsc::unbounded_channel::<GameMessage>();
+        let close_token = CancellationToken::new();
+
+        let conn = Connection {
+            username,
+            options: PlayOptions::default(),
+            rating: None,
+            local: true,
+            accept_tx: None,
+            close_token: close_token.clone(),
+            rx: im_rx,
+            tx: og_tx,
+        };
+        let peer = Peer {
+            close_token,
+            rx: og_rx,
+            tx: im_tx,
+        };
+        (conn, peer)
+    }
+
+    // the message isn't handed back, it's large and nobody needs it
+    pub fn send(&self, m: GameMessage) -> Result<(), SendError<()>> {
+        self.tx.send(m).map_err(|_| SendError(()))
     }
 
     pub async fn recv(&mut self) -> Option<GameMessage> {
         self.rx.recv().await
     }
 
+    // made by Connection::local rather than a websocket
+    pub fn is_local(&self) -> bool {
+        self.local
+    }
+
     pub fn close(&mut self) {
         self.close_token.cancel();
     }
@@ -53,7 +171,46 @@ impl Connection {
     }
 }
 
-pub async fn handle_connection(username: String, socket: WebSocket, conn_tx: ConnTx) {
+pub async fn handle_connection(
+    username: String,
+    options: PlayOptions,
+    socket: WebSocket,
+    conn_tx: ConnTx,
+) {
+    serve(username, options, Role::Player, socket, conn_tx).await;
+}
+
+pub async fn handle_spectator(game_id: usize, socket: WebSocket, conn_tx: ConnTx) {
+    let username = String::from("spectator");
+    serve(
+        username,
+        PlayOptions::default(),
+        Role::Spectator(game_id),
+        socket,
+        conn_tx,
+    )
+    .await;
+}
+
+pub async fn handle_observer(socket: WebSocket, conn_tx: ConnTx) {
+    let username = String::from("observer");
+    serve(
+        username,
+        PlayOptions::default(),
+        Role::Observer,
+        socket,
+        conn_tx,
+    )
+    .await;
+}
+
+async fn serve(
+    username: String,
+    options: PlayOptions,
+    role: Role,
+    socket: WebSocket,
+    conn_tx: ConnTx,
+) {
     let (im_tx, im_rx) = mpsc::unbounded_channel::<GameMessage>();
     let (og_tx, mut og_rx) = mpsc::unbounded_channel::<GameMessage>();
     let close_token = CancellationToken::new();
@@ -63,18 +220,23 @@ pub async fn handle_connection(username: String, socket: WebSocket, conn_tx: Con
 
     let conn = Connection {
         username: username.clone(),
+        options,
+        rating: None,
+        local: false,
         accept_tx: Some(accept_tx),
         close_token: close_token.clone(),
         rx: im_rx,
         tx: og_tx,
     };
-    match conn_tx.send(ConnectionUpdate::Connected(conn)) {
-        Err(_) => {
-            let _ = socket.close().await;
-            return;
-        }
-        Ok(()) => {}
+    let update = match role {
+        Role::Player => ConnectionUpdate::Connected(conn),
+        Role::Spectator(game_id) => ConnectionUpdate::Watching(game_id, conn),
+        Role::Observer => ConnectionUpdate::Observing(conn),
     };
+    if conn_tx.send(update).is_err() {
+        let _ = socket.close().await;
+        return;
+    }
 
     let (mut ws_tx, mut ws_rx) = socket.split();
 
@@ -91,7 +253,7 @@ pub async fn handle_connection(username: String, socket: WebSocket, conn_tx: Con
                             continue;
                         }
                     };
-                    if let Err(_) = ws_tx.send(WsMessage::text(text)).await {
+                    if ws_tx.send(WsMessage::text(text)).await.is_err() {
                         // eprintln!("websocket send error: {}", e);
                         og_token.cancel();
                         break;
@@ -134,6 +296,10 @@ pub async fn handle_connection(username: String, socket: WebSocket, conn_tx: Con
                 };
 
 
+                // spectators and observers are read-only
+                if !matches!(role, Role::Player) {
+                    continue;
+                }
                 if im_tx.send(msg).is_err() {
                     break;
                 };
@@ -146,7 +312,10 @@ pub async fn handle_connection(username: String, socket: WebSocket, conn_tx: Con
     // println!("Incoming Messages loop cancelled");
 
     close_token.cancel();
-    if let Ok(true) = accept_rx.await {
+    // only players are tracked by name, nothing else to clean up
+    if let Ok(true) = accept_rx.await
+        && matches!(role, Role::Player)
+    {
         let _ = conn_tx.send(ConnectionUpdate::Disconnected(username));
     }
 }
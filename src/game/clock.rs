@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::connect4::Color;
+
+// whole seconds, a missing limit is never enforced
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeControl {
+    // limit on every single move
+    pub move_time: Option<u32>,
+    // Fischer clock: total time per player, topped up after each move
+    pub clock: Option<u32>,
+    pub increment: u32,
+}
+
+// milliseconds each player has for their current or next move
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TimeLeft {
+    pub red: u64,
+    pub blue: u64,
+}
+
+#[derive(Debug)]
+pub struct Clock {
+    control: TimeControl,
+    red_bank: Duration,
+    blue_bank: Duration,
+    turn: Color,
+    turn_started: Instant,
+}
+
+impl TimeControl {
+    pub fn is_timed(&self) -> bool {
+        self.move_time.is_some() || self.clock.is_some()
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let bank = Duration::from_secs(control.clock.unwrap_or(0).into());
+        Self {
+            control,
+            red_bank: bank,
+            blue_bank: bank,
+            turn: Color::Red,
+            turn_started: Instant::now(),
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    // restarts the current player's move, called once the game begins
+    pub fn start(&mut self) {
+        self.turn_started = Instant::now();
+    }
+
+    // when the player to move loses on time, None for untimed games
+    pub fn deadline(&self) -> Option<Instant> {
+        self.allowance(self.turn)
+            .map(|allowance| self.turn_started + allowance)
+    }
+
+    pub fn flag_fell(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // charges the mover for their move and starts the opponent's
+    pub fn moved(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.turn_started;
+        let increment = Duration::from_secs(self.control.increment.into());
+        if self.control.clock.is_some() {
+            let bank = self.bank_mut(self.turn);
+            *bank = bank.saturating_sub(elapsed) + increment;
+        }
+        self.turn = self.turn.toggle();
+        self.turn_started = now;
+    }
+
+    // hands the turn back after an undo, the player who agreed to it isn't
+    // charged for the time and the undone move's increment goes with it
+    pub fn taken_back(&mut self) {
+        self.turn = self.turn.toggle();
+        self.turn_started = Instant::now();
+        if self.control.clock.is_some() {
+            let increment = Duration::from_secs(self.control.increment.into());
+            let bank = self.bank_mut(self.turn);
+            *bank = bank.saturating_sub(increment);
+        }
+    }
+
+    pub fn time_left(&self) -> Option<TimeLeft> {
+        let elapsed = self.turn_started.elapsed();
+        let left = |color: Color| {
+            let allowance = self.allowance(color).unwrap_or_default();
+            let left = match color == self.turn {
+                true => allowance.saturating_sub(elapsed),
+                false => allowance,
+            };
+            left.as_millis() as u64
+        };
+        match self.control.is_timed() {
+            true => Some(TimeLeft {
+                red: left(Color::Red),
+                blue: left(Color::Blue),
+            }),
+            false => None,
+        }
+    }
+
+    fn allowance(&self, color: Color) -> Option<Duration> {
+        let bank = self.control.clock.map(|_| match color {
+            Color::Red => self.red_bank,
+            Color::Blue => self.blue_bank,
+        });
+        let move_time = self
+            .control
+            .move_time
+            .map(|s| Duration::from_secs(s.into()));
+        match (bank, move_time) {
+            (Some(bank), Some(move_time)) => Some(bank.min(move_time)),
+            (bank, move_time) => bank.or(move_time),
+        }
+    }
+
+    fn bank_mut(&mut self, color: Color) -> &mut Duration {
+        match color {
+            Color::Red => &mut self.red_bank,
+            Color::Blue => &mut self.blue_bank,
+        }
+    }
+}
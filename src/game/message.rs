@@ -1,43 +1,228 @@
 use serde::{Deserialize, Serialize};
 
-use crate::connect4::{BoardLayout, Color, Move, PlayError};
+use crate::connect4::{BoardLayout, Cell, Color, Move, PlayError, Rules, RulesError};
+use crate::game::ChatError;
+use crate::game::clock::{TimeControl, TimeLeft};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::enum_variant_names)]
 pub enum Message {
     // input
     DropChip {
         column: usize,
     },
+    // PopOut only
+    PopChip {
+        column: usize,
+    },
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    // take back the last move, only its mover can ask
+    RequestUndo,
+    AcceptUndo,
+    DeclineUndo,
+    // to the opponent and spectators, during or right after a game
+    Chat {
+        text: String,
+    },
+    // once the game is over, play again with colors swapped
+    Rematch,
+    DeclineRematch,
+    // while waiting for a match
+    Challenge {
+        opponent: String,
+    },
+    AcceptChallenge {
+        from: String,
+    },
+    DeclineChallenge {
+        from: String,
+    },
 
     // output
     MatchMade {
         your_username: String,
         your_color: Color,
+        your_rating: Option<i32>,
         opponent_username: String,
+        opponent_rating: Option<i32>,
+        rules: Rules,
+        time_control: TimeControl,
+        // reconnect with ?resume= to pick the game back up
+        resume_token: String,
     },
     Board {
         turn: Color,
+        rules: Rules,
         board: BoardLayout,
+        time: Option<TimeLeft>,
+        history: Vec<Move>,
     },
     Moved {
         last_mover: Color,
         last_move: Move,
         board: BoardLayout,
+        time: Option<TimeLeft>,
     },
     Won {
         winner: Color,
+        // every cell of the winning line, or lines
+        line: Vec<Cell>,
         last_move: Move,
         board: BoardLayout,
+        history: Vec<Move>,
     },
     Stalemate {
         last_move: Move,
         board: BoardLayout,
+        history: Vec<Move>,
+    },
+    // the player to move ran out of time
+    TimedOut {
+        winner: Color,
+        board: BoardLayout,
+    },
+    Resigned {
+        winner: Color,
+        board: BoardLayout,
+    },
+    // the loser left and never came back
+    Forfeited {
+        winner: Color,
+        board: BoardLayout,
+    },
+    DrawOffered {
+        by: Color,
+    },
+    DrawDeclined {
+        by: Color,
+    },
+    DrawAgreed {
+        board: BoardLayout,
+    },
+    UndoRequested {
+        by: Color,
+    },
+    UndoDeclined {
+        by: Color,
+    },
+    // after every board of a series, wins line up with players
+    SeriesScore {
+        best_of: u32,
+        played: u32,
+        players: [String; 2],
+        wins: [u32; 2],
+    },
+    // None for a tied series
+    SeriesOver {
+        winner: Option<String>,
+        players: [String; 2],
+        wins: [u32; 2],
     },
+    RematchOffered {
+        by: Color,
+    },
+    RematchDeclined {
+        by: Color,
+    },
+    // a Board snapshot follows
+    Undone {
+        undone: Move,
+    },
+    ChatMessage {
+        from: String,
+        text: String,
+    },
+    OpponentLeft,
+    OpponentReturned,
+    // first message to a spectator, a Board snapshot follows
+    Spectating {
+        red_username: String,
+        blue_username: String,
+        rules: Rules,
+    },
+    UnknownGame,
+    RoomCreated {
+        code: String,
+    },
+    UnknownRoom,
+    // everyone waiting for a match, sent whenever that changes
+    WaitingUsers {
+        users: Vec<WaitingUser>,
+    },
+    Challenged {
+        from: String,
+        rating: i32,
+        rules: Rules,
+        time_control: TimeControl,
+        best_of: u32,
+    },
+    ChallengeDeclined {
+        by: String,
+    },
+    UnknownOpponent,
+    // the whole picture for /lobby, sent again whenever any of it changes
+    LobbyStatus {
+        waiting: Vec<WaitingUser>,
+        games: Vec<LiveGame>,
+        // newest first
+        results: Vec<FinishedGame>,
+    },
+    // 1 is next in line, the estimate is in seconds once anyone's been matched
+    Queued {
+        position: usize,
+        estimated_wait: Option<u64>,
+    },
+    // connected for a tournament with no game to play yet this round
+    TournamentWaiting {
+        tournament: usize,
+        round: usize,
+    },
+    // None for a round robin that ended level on points
+    TournamentOver {
+        tournament: usize,
+        winner: Option<String>,
+    },
+    // no such tournament, or not on its roster
+    UnknownTournament,
     RepeatUsername,
+    InvalidRules(RulesError),
+    InvalidResumeToken,
+    InvalidSeries,
+    InvalidChat(ChatError),
     InvalidFormat,
     InvalidMessage,
     InvalidMove(PlayError),
 
     TooManyPlayers, // not necessary >:)
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaitingUser {
+    pub username: String,
+    pub rating: i32,
+    pub rules: Rules,
+    pub time_control: TimeControl,
+    pub best_of: u32,
+}
+
+// players by the colors they started with, a series swaps them every board
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiveGame {
+    pub id: usize,
+    pub red: String,
+    pub blue: String,
+    pub best_of: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FinishedGame {
+    pub id: usize,
+    pub red: String,
+    pub blue: String,
+    // None for a draw
+    pub winner: Option<String>,
+}
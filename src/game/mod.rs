@@ -1,112 +1,335 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::sync::mpsc::error::SendError;
-use tokio_util::sync::CancellationToken;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 
 use crate::Connection;
-use crate::connect4::{Board, BoardState, Color, PlayError};
+use crate::connect4::{Board, BoardState, Color, MoveKind, PlayError, Rules};
+use crate::game::clock::{Clock, TimeControl};
 use crate::game::message::Message;
 
+pub mod clock;
 pub mod message;
+mod test;
+
+// how long both players have to agree to play again once a game ends
+const REMATCH_WINDOW: Duration = Duration::from_secs(30);
+// longer chat messages are turned away, in characters
+const CHAT_MAX_LEN: usize = 200;
+// each player gets one chat message this often
+const CHAT_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum GameStatus {
     Playing,
     GameWon(String),
     Stalemate,
+    // the winner, their opponent's flag fell
+    TimedOut(String),
+    // the winner, their opponent gave up
+    Resigned(String),
+    // the winner, their opponent left and didn't come back in time
+    Forfeited(String),
+    DrawAgreed,
 }
 
 #[derive(Debug, Error)]
 pub enum GameError {
     // fatal
-    #[error("a connection closed before notification")]
+    #[error("every connection closed")]
     ConnectionError,
-    #[error("the game was cancelled")]
-    GameCancelled,
 }
 
+#[derive(Clone, Debug, Error, Deserialize, Serialize)]
+pub enum ChatError {
+    #[error("chat message is empty")]
+    Empty,
+    #[error("chat message is longer than {0} characters")]
+    TooLong(usize),
+    #[error("chat messages are sent too quickly")]
+    TooFast,
+}
+
+// sent by the lobby while the game runs
+#[derive(Debug)]
+pub enum GameEvent {
+    // the player's websocket dropped, they may still come back
+    Left(String),
+    // the same player is back on a new connection
+    Rejoined(Connection),
+    Spectate(Connection),
+    // the player stayed away past their grace period, they lose
+    Forfeit(String),
+}
+
+pub type GameEventTx = mpsc::UnboundedSender<GameEvent>;
+pub type GameEventRx = mpsc::UnboundedReceiver<GameEvent>;
+
 #[derive(Debug)]
 pub struct Game {
     id: usize,
     board: Board,
-    cancel: CancellationToken,
+    clock: Clock,
+    // who has a draw offer standing
+    draw_offer: Option<Color>,
+    // who asked to take back their last move
+    undo_request: Option<Color>,
+    events: GameEventRx,
     red: Connection,
     blue: Connection,
+    // players that went away aren't sent anything until they rejoin
+    red_away: bool,
+    blue_away: bool,
+    // proves a reconnecting player is the one who left
+    red_token: String,
+    blue_token: String,
+    // when each player last chatted, for rate limiting
+    red_chatted: Option<Instant>,
+    blue_chatted: Option<Instant>,
+    // read-only, they get everything that's broadcast
+    spectators: Vec<Connection>,
 }
 
 impl Game {
-    pub fn new(id: usize, cancel: CancellationToken, red: Connection, blue: Connection) -> Self {
+    pub fn new(
+        id: usize,
+        events: GameEventRx,
+        rules: Rules,
+        time_control: TimeControl,
+        red: Connection,
+        blue: Connection,
+    ) -> Self {
         Self {
             id,
-            board: Board::new(),
-            cancel,
+            board: Board::with_rules(rules),
+            clock: Clock::new(time_control),
+            draw_offer: None,
+            undo_request: None,
+            events,
             red,
             blue,
+            red_away: false,
+            blue_away: false,
+            red_token: resume_token(),
+            blue_token: resume_token(),
+            red_chatted: None,
+            blue_chatted: None,
+            spectators: Vec::new(),
         }
     }
 
     // TODO: every expect should return an error that kills the game loop+program
 
     pub async fn play(&mut self) -> Result<GameStatus, GameError> {
+        let deadline = self.clock.deadline();
         tokio::select! {
             Some(message) = self.red.recv() => {
-                return self.play_message(Color::Red, message);
+                self.play_message(Color::Red, message)
             }
             Some(message) = self.blue.recv() => {
-                return self.play_message(Color::Blue, message);
+                self.play_message(Color::Blue, message)
+            }
+            _ = sleep_until(deadline) => {
+                self.time_out()
             }
-            _ = self.cancel.cancelled() => {
-                // This will cause the game play to stop from the
-                // game thread. Afterwhich, the kick function
-                // should be called from the game thread
-                return Err(GameError::GameCancelled);
+            Some(event) = self.events.recv() => {
+                self.game_event(event)
+            }
+        }
+    }
+
+    // after the game ends both players may ask to play again, true once
+    // they both have
+    pub async fn rematch(&mut self) -> bool {
+        if self.red_away || self.blue_away {
+            return false;
+        }
+        let deadline = Instant::now() + REMATCH_WINDOW;
+        let mut offer: Option<Color> = None;
+        loop {
+            let (from, msg) = tokio::select! {
+                msg = self.red.recv() => (Color::Red, msg),
+                msg = self.blue.recv() => (Color::Blue, msg),
+                _ = tokio::time::sleep_until(deadline) => return false,
+                Some(event) = self.events.recv() => match event {
+                    GameEvent::Left(username) => match self.color_of(&username) {
+                        Some(color) => (color, None),
+                        None => continue,
+                    },
+                    // nothing left to rejoin or watch
+                    GameEvent::Rejoined(mut conn) | GameEvent::Spectate(mut conn) => {
+                        conn.close();
+                        continue;
+                    }
+                    GameEvent::Forfeit(_) => continue,
+                },
+            };
+            match msg {
+                Some(Message::Rematch) => match offer {
+                    Some(by) if by != from => return true,
+                    Some(_) => {}
+                    None => {
+                        offer = Some(from);
+                        let _ = self.broadcast(Message::RematchOffered { by: from });
+                    }
+                },
+                Some(Message::Chat { text }) => {
+                    let _ = self.chat(from, text);
+                }
+                // leaving turns it down too
+                Some(Message::DeclineRematch) | None => {
+                    let _ = self.broadcast(Message::RematchDeclined { by: from });
+                    return false;
+                }
+                Some(_) => {
+                    let _ = self.reply(from, Message::InvalidMessage);
+                }
             }
         }
     }
 
+    // starts the next board of a series, the players swap colors so the
+    // other one moves first
+    pub async fn next_board(&mut self) -> Result<(), GameError> {
+        std::mem::swap(&mut self.red, &mut self.blue);
+        std::mem::swap(&mut self.red_away, &mut self.blue_away);
+        std::mem::swap(&mut self.red_token, &mut self.blue_token);
+        self.board = Board::with_rules(self.board.rules());
+        self.clock = Clock::new(self.clock.control());
+        self.draw_offer = None;
+        self.undo_request = None;
+        self.game_start().await
+    }
+
+    // hands the players back for a rematch, spectators are let go
+    pub fn into_players(mut self) -> (Connection, Connection) {
+        for spectator in self.spectators.iter_mut() {
+            spectator.close();
+        }
+        (self.red, self.blue)
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
 
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        self.clock.control()
+    }
+
+    pub fn resume_token(&self, color: Color) -> &str {
+        match color {
+            Color::Red => &self.red_token,
+            Color::Blue => &self.blue_token,
+        }
+    }
+
+    fn game_event(&mut self, event: GameEvent) -> Result<GameStatus, GameError> {
+        match event {
+            GameEvent::Left(username) => {
+                let Some(color) = self.color_of(&username) else {
+                    return Ok(GameStatus::Playing);
+                };
+                *self.away_mut(color) = true;
+                self.reply(color.toggle(), Message::OpponentLeft)
+            }
+            GameEvent::Rejoined(mut conn) => {
+                let Some(color) = self.color_of(&conn.username) else {
+                    conn.close();
+                    return Ok(GameStatus::Playing);
+                };
+                let mut old = match color {
+                    Color::Red => std::mem::replace(&mut self.red, conn),
+                    Color::Blue => std::mem::replace(&mut self.blue, conn),
+                };
+                old.close();
+                *self.away_mut(color) = false;
+
+                // everything they need to pick the game back up
+                self.send_to(color, self.match_made(color));
+                let time = self.clock.time_left();
+                self.send_to(color, Message::board(&self.board, time));
+                if let Some(by) = self.draw_offer {
+                    self.send_to(color, Message::DrawOffered { by });
+                }
+                if let Some(by) = self.undo_request {
+                    self.send_to(color, Message::UndoRequested { by });
+                }
+                self.reply(color.toggle(), Message::OpponentReturned)
+            }
+            GameEvent::Spectate(conn) => {
+                let watching = Message::Spectating {
+                    red_username: self.red.username.clone(),
+                    blue_username: self.blue.username.clone(),
+                    rules: self.board.rules(),
+                };
+                let board = Message::board(&self.board, self.clock.time_left());
+                if conn.send(watching).is_ok() && conn.send(board).is_ok() {
+                    self.spectators.push(conn);
+                }
+                Ok(GameStatus::Playing)
+            }
+            GameEvent::Forfeit(username) => match self.color_of(&username) {
+                // they may have made it back just in time
+                Some(color) if *self.away_mut(color) => self.forfeit(color),
+                _ => Ok(GameStatus::Playing),
+            },
+        }
+    }
+
     // made this function so I wouldn't have to write code inside that select macro
     // autocompletes are super slow in there
     fn play_message(&mut self, from: Color, msg: Message) -> Result<GameStatus, GameError> {
-        let conn = match from {
-            Color::Red => &self.red,
-            Color::Blue => &self.blue,
-        };
-        let column = match msg {
-            Message::DropChip { column } => column,
-            _ => {
-                let invalid_message_msg = Message::InvalidMessage;
-                if let Err(_) = conn.send(invalid_message_msg) {
-                    return Err(GameError::ConnectionError);
-                }
-                return Ok(GameStatus::Playing);
-            }
-        };
-        match self.board.drop_chip(from, column) {
+        // the message may have arrived just as the flag fell
+        if self.board.state() == BoardState::Turn(from) && self.clock.flag_fell() {
+            return self.time_out();
+        }
+        match msg {
+            Message::DropChip { column } => self.play_move(from, MoveKind::Drop, column),
+            Message::PopChip { column } => self.play_move(from, MoveKind::Pop, column),
+            Message::Resign => self.resign(from),
+            Message::OfferDraw => self.offer_draw(from),
+            Message::AcceptDraw => self.accept_draw(from),
+            Message::DeclineDraw => self.decline_draw(from),
+            Message::RequestUndo => self.request_undo(from),
+            Message::AcceptUndo => self.accept_undo(from),
+            Message::DeclineUndo => self.decline_undo(from),
+            Message::Chat { text } => self.chat(from, text),
+            _ => self.reply(from, Message::InvalidMessage),
+        }
+    }
+
+    fn play_move(
+        &mut self,
+        from: Color,
+        kind: MoveKind,
+        column: usize,
+    ) -> Result<GameStatus, GameError> {
+        match self.board.play(from, kind, column) {
             Ok(drop_res) => match drop_res.state {
                 BoardState::Turn(_) => {
-                    if let Err(_) =
-                        self.broadcast(Message::moved(&self.board, drop_res.last_move, from))
-                    {
-                        return Err(GameError::ConnectionError);
+                    self.clock.moved();
+                    // moving instead of answering turns the offer down
+                    if self.draw_offer == Some(from.toggle()) {
+                        self.draw_offer = None;
                     }
+                    // the move asked about isn't the last one anymore
+                    self.undo_request = None;
+                    let time = self.clock.time_left();
+                    self.broadcast(Message::moved(&self.board, drop_res.last_move, from, time))?;
                 } // transition to game over state!
                 BoardState::Won(winner) => {
-                    if let Err(_) = self.broadcast(Message::won(&self.board, winner)) {
-                        return Err(GameError::ConnectionError);
-                    }
-                    let w_username = match winner {
-                        Color::Red => self.red.username.clone(),
-                        Color::Blue => self.blue.username.clone(),
-                    };
-                    return Ok(GameStatus::GameWon(w_username));
+                    self.broadcast(Message::won(&self.board, winner))?;
+                    return Ok(GameStatus::GameWon(self.username(winner)));
                 }
                 BoardState::Stalemate => {
-                    if let Err(_) = self.broadcast(Message::stalemate(&self.board)) {
-                        return Err(GameError::ConnectionError);
-                    }
+                    self.broadcast(Message::stalemate(&self.board))?;
                     return Ok(GameStatus::Stalemate);
                 }
             },
@@ -116,36 +339,242 @@ impl Game {
                     PlayError::Stalemate => Message::stalemate(&self.board),
                     play_err => Message::InvalidMove(play_err),
                 };
-                if let Err(_) = conn.send(feedback_msg) {
-                    return Err(GameError::ConnectionError);
-                }
+                return self.reply(from, feedback_msg);
             }
         };
         Ok(GameStatus::Playing)
     }
 
-    fn broadcast(&self, msg: Message) -> Result<(), SendError<Message>> {
-        self.red.send(msg.clone())?;
-        self.blue.send(msg.clone())?;
-        Ok(())
+    fn time_out(&mut self) -> Result<GameStatus, GameError> {
+        let BoardState::Turn(loser) = self.board.state() else {
+            unreachable!();
+        };
+        let winner = loser.toggle();
+        self.broadcast(Message::TimedOut {
+            winner,
+            board: self.board.layout(),
+        })?;
+        Ok(GameStatus::TimedOut(self.username(winner)))
     }
 
-    pub async fn game_start(&self) -> Result<(), SendError<Message>> {
-        self.red.send(Message::MatchMade {
-            your_username: self.red.username.clone(),
-            your_color: Color::Red,
-            opponent_username: self.blue.username.clone(),
+    fn resign(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        let winner = from.toggle();
+        self.broadcast(Message::Resigned {
+            winner,
+            board: self.board.layout(),
         })?;
-        self.blue.send(Message::MatchMade {
-            your_username: self.blue.username.clone(),
-            your_color: Color::Blue,
-            opponent_username: self.red.username.clone(),
+        Ok(GameStatus::Resigned(self.username(winner)))
+    }
+
+    fn forfeit(&mut self, loser: Color) -> Result<GameStatus, GameError> {
+        let winner = loser.toggle();
+        // the result stands even if the winner has gone too
+        let _ = self.broadcast(Message::Forfeited {
+            winner,
+            board: self.board.layout(),
+        });
+        Ok(GameStatus::Forfeited(self.username(winner)))
+    }
+
+    fn offer_draw(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        match self.draw_offer {
+            // both want a draw, no need to ask again
+            Some(by) if by != from => return self.accept_draw(from),
+            Some(_) => return self.reply(from, Message::InvalidMessage),
+            None => {}
+        }
+        self.draw_offer = Some(from);
+        self.broadcast(Message::DrawOffered { by: from })?;
+        Ok(GameStatus::Playing)
+    }
+
+    fn accept_draw(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        if self.draw_offer != Some(from.toggle()) {
+            return self.reply(from, Message::InvalidMessage);
+        }
+        self.broadcast(Message::DrawAgreed {
+            board: self.board.layout(),
         })?;
-        self.broadcast(Message::board(&self.board))
+        Ok(GameStatus::DrawAgreed)
+    }
+
+    fn decline_draw(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        if self.draw_offer != Some(from.toggle()) {
+            return self.reply(from, Message::InvalidMessage);
+        }
+        self.draw_offer = None;
+        self.broadcast(Message::DrawDeclined { by: from })?;
+        Ok(GameStatus::Playing)
+    }
+
+    fn request_undo(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        let last_mover = self.board.history().last().map(|m| m.color());
+        if last_mover != Some(from) || self.undo_request.is_some() {
+            return self.reply(from, Message::InvalidMessage);
+        }
+        self.undo_request = Some(from);
+        self.broadcast(Message::UndoRequested { by: from })?;
+        Ok(GameStatus::Playing)
+    }
+
+    fn accept_undo(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        if self.undo_request != Some(from.toggle()) {
+            return self.reply(from, Message::InvalidMessage);
+        }
+        self.undo_request = None;
+        let Some(undone) = self.board.undo() else {
+            unreachable!(); // requests need a last move
+        };
+        self.clock.taken_back();
+        self.broadcast(Message::Undone { undone })?;
+        let time = self.clock.time_left();
+        self.broadcast(Message::board(&self.board, time))?;
+        Ok(GameStatus::Playing)
+    }
+
+    fn decline_undo(&mut self, from: Color) -> Result<GameStatus, GameError> {
+        if self.undo_request != Some(from.toggle()) {
+            return self.reply(from, Message::InvalidMessage);
+        }
+        self.undo_request = None;
+        self.broadcast(Message::UndoDeclined { by: from })?;
+        Ok(GameStatus::Playing)
+    }
+
+    // relayed to everyone, the sender included so they see what was sent
+    fn chat(&mut self, from: Color, text: String) -> Result<GameStatus, GameError> {
+        let text = text.trim();
+        let error = if text.is_empty() {
+            Some(ChatError::Empty)
+        } else if text.chars().count() > CHAT_MAX_LEN {
+            Some(ChatError::TooLong(CHAT_MAX_LEN))
+        } else if self
+            .chatted_mut(from)
+            .is_some_and(|at| at.elapsed() < CHAT_INTERVAL)
+        {
+            Some(ChatError::TooFast)
+        } else {
+            None
+        };
+        if let Some(e) = error {
+            return self.reply(from, Message::InvalidChat(e));
+        }
+        *self.chatted_mut(from) = Some(Instant::now());
+        self.broadcast(Message::ChatMessage {
+            from: self.username(from),
+            text: text.to_string(),
+        })?;
+        Ok(GameStatus::Playing)
+    }
+
+    // sends to a single player, the game goes on either way
+    fn reply(&mut self, to: Color, msg: Message) -> Result<GameStatus, GameError> {
+        self.send_to(to, msg);
+        Ok(GameStatus::Playing)
+    }
+
+    // a failed send means the player dropped, they get a snapshot if they
+    // come back so nothing is lost
+    fn send_to(&mut self, to: Color, msg: Message) {
+        if *self.away_mut(to) {
+            return;
+        }
+        let conn = match to {
+            Color::Red => &self.red,
+            Color::Blue => &self.blue,
+        };
+        if conn.send(msg).is_err() {
+            *self.away_mut(to) = true;
+        }
+    }
+
+    fn away_mut(&mut self, color: Color) -> &mut bool {
+        match color {
+            Color::Red => &mut self.red_away,
+            Color::Blue => &mut self.blue_away,
+        }
+    }
+
+    fn chatted_mut(&mut self, color: Color) -> &mut Option<Instant> {
+        match color {
+            Color::Red => &mut self.red_chatted,
+            Color::Blue => &mut self.blue_chatted,
+        }
+    }
+
+    // bots can share a player's name, only players reconnect
+    fn color_of(&self, username: &str) -> Option<Color> {
+        if !self.red.is_local() && self.red.username == username {
+            Some(Color::Red)
+        } else if !self.blue.is_local() && self.blue.username == username {
+            Some(Color::Blue)
+        } else {
+            None
+        }
+    }
+
+    pub fn username(&self, color: Color) -> String {
+        match color {
+            Color::Red => self.red.username.clone(),
+            Color::Blue => self.blue.username.clone(),
+        }
+    }
+
+    // only fails once nobody is left to play
+    pub fn broadcast(&mut self, msg: Message) -> Result<(), GameError> {
+        // spectators that left are simply forgotten
+        self.spectators
+            .retain(|spectator| spectator.send(msg.clone()).is_ok());
+        self.send_to(Color::Red, msg.clone());
+        self.send_to(Color::Blue, msg);
+        if self.red_away && self.blue_away {
+            return Err(GameError::ConnectionError);
+        }
+        Ok(())
+    }
+
+    fn match_made(&self, color: Color) -> Message {
+        let (you, opponent) = match color {
+            Color::Red => (&self.red, &self.blue),
+            Color::Blue => (&self.blue, &self.red),
+        };
+        Message::MatchMade {
+            your_username: you.username.clone(),
+            your_color: color,
+            your_rating: you.rating,
+            opponent_username: opponent.username.clone(),
+            opponent_rating: opponent.rating,
+            rules: self.board.rules(),
+            time_control: self.clock.control(),
+            resume_token: self.resume_token(color).to_string(),
+        }
+    }
+
+    pub async fn game_start(&mut self) -> Result<(), GameError> {
+        for color in [Color::Red, Color::Blue] {
+            self.send_to(color, self.match_made(color));
+        }
+        self.clock.start();
+        self.broadcast(Message::board(&self.board, self.clock.time_left()))
     }
 
     pub fn game_over(&mut self) {
         self.red.close();
         self.blue.close();
+        for spectator in self.spectators.iter_mut() {
+            spectator.close();
+        }
+    }
+}
+
+// never wakes for untimed games
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
     }
 }
+
+fn resume_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
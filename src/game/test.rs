@@ -0,0 +1,221 @@
+#[allow(unused_imports)]
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use tokio::sync::mpsc;
+#[allow(unused_imports)]
+use tokio::time::Instant;
+
+#[allow(unused_imports)]
+use crate::connect4::Rules;
+#[allow(unused_imports)]
+use crate::connection::{Connection, Peer};
+#[allow(unused_imports)]
+use crate::game::{
+    ChatError, Game, GameEvent, GameEventTx,
+    clock::{Clock, TimeControl},
+    message::Message,
+};
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_taken_back_removes_increment() {
+    let mut clock = Clock::new(TimeControl {
+        move_time: None,
+        clock: Some(60),
+        increment: 5,
+    });
+    clock.start();
+    tokio::time::advance(Duration::from_secs(10)).await;
+    clock.moved();
+    assert_eq!(clock.time_left().unwrap().red, 55_000);
+
+    // red's move is undone, they keep the time they spent but not the bonus
+    tokio::time::advance(Duration::from_secs(3)).await;
+    clock.taken_back();
+    let left = clock.time_left().unwrap();
+    assert_eq!((left.red, left.blue), (50_000, 60_000));
+}
+
+#[allow(unused)] // used by tests
+fn control(move_time: Option<u32>, clock: Option<u32>, increment: u32) -> TimeControl {
+    TimeControl {
+        move_time,
+        clock,
+        increment,
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_untimed() {
+    let mut clock = Clock::new(TimeControl::default());
+    clock.start();
+    tokio::time::advance(Duration::from_secs(3600)).await;
+    assert_eq!(clock.deadline(), None);
+    assert!(!clock.flag_fell());
+    assert!(clock.time_left().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_deadline() {
+    let start = Instant::now();
+    // the per-move limit caps a bigger bank
+    let mut clock = Clock::new(control(Some(10), Some(60), 0));
+    clock.start();
+    assert_eq!(clock.deadline(), Some(start + Duration::from_secs(10)));
+
+    // the deadline follows the player to move and their own bank
+    tokio::time::advance(Duration::from_secs(4)).await;
+    clock.moved();
+    assert_eq!(clock.deadline(), Some(start + Duration::from_secs(14)));
+
+    // a bank smaller than the per-move limit caps it instead
+    let mut clock = Clock::new(control(Some(10), Some(5), 0));
+    clock.start();
+    assert_eq!(clock.deadline(), Some(start + Duration::from_secs(9)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_move_time_runs_out() {
+    let mut clock = Clock::new(control(Some(5), None, 0));
+    clock.start();
+    tokio::time::advance(Duration::from_secs(4)).await;
+    assert!(!clock.flag_fell());
+    // every move gets the full limit again
+    clock.moved();
+    tokio::time::advance(Duration::from_secs(4)).await;
+    assert!(!clock.flag_fell());
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(clock.flag_fell());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_bank_runs_out() {
+    let mut clock = Clock::new(control(None, Some(10), 0));
+    clock.start();
+    tokio::time::advance(Duration::from_secs(6)).await;
+    clock.moved();
+    tokio::time::advance(Duration::from_secs(1)).await;
+    clock.moved();
+    // red only has what's left of their bank
+    let left = clock.time_left().unwrap();
+    assert_eq!((left.red, left.blue), (4_000, 9_000));
+    tokio::time::advance(Duration::from_millis(3_999)).await;
+    assert!(!clock.flag_fell());
+    tokio::time::advance(Duration::from_millis(1)).await;
+    assert!(clock.flag_fell());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_both_limits_run_out() {
+    let mut clock = Clock::new(control(Some(5), Some(8), 0));
+    clock.start();
+    tokio::time::advance(Duration::from_secs(4)).await;
+    clock.moved();
+    clock.moved();
+    // 4 seconds left in the bank, less than the per-move limit
+    tokio::time::advance(Duration::from_secs(4)).await;
+    assert!(clock.flag_fell());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_clock_increment() {
+    let mut clock = Clock::new(control(None, Some(10), 3));
+    clock.start();
+    // moves faster than the increment build up the bank
+    tokio::time::advance(Duration::from_secs(1)).await;
+    clock.moved();
+    tokio::time::advance(Duration::from_secs(2)).await;
+    clock.moved();
+    let left = clock.time_left().unwrap();
+    assert_eq!((left.red, left.blue), (12_000, 11_000));
+
+    // an overdrawn bank bottoms out at zero before the increment
+    tokio::time::advance(Duration::from_secs(20)).await;
+    assert!(clock.flag_fell());
+    clock.moved();
+    assert_eq!(clock.time_left().unwrap().red, 3_000);
+
+    // without a bank there's nothing to add to
+    let mut clock = Clock::new(control(Some(5), None, 3));
+    clock.start();
+    clock.moved();
+    assert_eq!(clock.time_left().unwrap().red, 5_000);
+}
+
+// a game between alice and bob, driven from their ends of the connections
+#[allow(unused)] // used by tests
+fn local_game() -> (Game, Peer, Peer, GameEventTx) {
+    let (red, red_peer) = Connection::local("alice".to_string());
+    let (blue, blue_peer) = Connection::local("bob".to_string());
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let rules = Rules::new(7, 6, 4).unwrap();
+    let game = Game::new(0, events_rx, rules, TimeControl::default(), red, blue);
+    (game, red_peer, blue_peer, events_tx)
+}
+
+// red sends a chat message and the game handles it
+#[allow(unused)] // used by tests
+async fn chat(game: &mut Game, red: &Peer, text: &str) {
+    red.send(Message::Chat {
+        text: text.to_string(),
+    })
+    .unwrap();
+    game.play().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_chat_rejections() {
+    let (mut game, mut red, _blue, _events) = local_game();
+
+    chat(&mut game, &red, &"a".repeat(201)).await;
+    assert!(matches!(
+        red.recv().await,
+        Some(Message::InvalidChat(ChatError::TooLong(200)))
+    ));
+    // only whitespace is as good as nothing
+    chat(&mut game, &red, "  \n ").await;
+    assert!(matches!(
+        red.recv().await,
+        Some(Message::InvalidChat(ChatError::Empty))
+    ));
+
+    // rejected messages don't count towards the rate limit
+    chat(&mut game, &red, "hi").await;
+    assert!(matches!(
+        red.recv().await,
+        Some(Message::ChatMessage { .. })
+    ));
+    tokio::time::advance(Duration::from_millis(999)).await;
+    chat(&mut game, &red, "hi again").await;
+    assert!(matches!(
+        red.recv().await,
+        Some(Message::InvalidChat(ChatError::TooFast))
+    ));
+    tokio::time::advance(Duration::from_millis(1)).await;
+    chat(&mut game, &red, "hi again").await;
+    assert!(matches!(
+        red.recv().await,
+        Some(Message::ChatMessage { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_chat_relayed_to_spectators() {
+    let (mut game, red, mut blue, events) = local_game();
+    let (spectator, mut watcher) = Connection::local("carol".to_string());
+    events.send(GameEvent::Spectate(spectator)).unwrap();
+    game.play().await.unwrap();
+    assert!(matches!(
+        watcher.recv().await,
+        Some(Message::Spectating { .. })
+    ));
+    assert!(matches!(watcher.recv().await, Some(Message::Board { .. })));
+
+    chat(&mut game, &red, " good luck ").await;
+    for peer in [&mut watcher, &mut blue] {
+        assert!(matches!(
+            peer.recv().await,
+            Some(Message::ChatMessage { from, text }) if from == "alice" && text == "good luck"
+        ));
+    }
+}
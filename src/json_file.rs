@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+// state that lives in a single JSON file, read whole at startup and
+// rewritten whole on every change
+
+#[derive(Debug, Error)]
+pub enum JsonFileError {
+    #[error("couldn't be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+// a missing file is the same as an empty one
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T, JsonFileError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// write then rename, so a crash never leaves half a file behind
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), JsonFileError> {
+    let text = serde_json::to_string_pretty(value)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+enum Job<T> {
+    Write(T),
+    // answered once everything sent before it is on disk
+    Flush(oneshot::Sender<()>),
+}
+
+// saves in the background so nobody waits on the disk, one write at a time
+// so they can't overtake each other, only the newest of any that queue up
+// is written
+#[derive(Debug)]
+pub struct Writer<T> {
+    jobs: mpsc::UnboundedSender<Job<T>>,
+}
+
+impl<T: Serialize + Send + 'static> Writer<T> {
+    // must be called from within the runtime, the name is for the logs
+    pub fn spawn(path: PathBuf, name: &'static str) -> Self {
+        let (jobs, mut queued) = mpsc::unbounded_channel::<Job<T>>();
+        tokio::task::spawn(async move {
+            while let Some(job) = queued.recv().await {
+                let mut latest = None;
+                let mut flushes = Vec::new();
+                let mut next = Some(job);
+                while let Some(job) = next {
+                    match job {
+                        Job::Write(value) => latest = Some(value),
+                        Job::Flush(done) => flushes.push(done),
+                    }
+                    next = queued.try_recv().ok();
+                }
+                if let Some(value) = latest {
+                    let path = path.clone();
+                    match tokio::task::spawn_blocking(move || save(&path, &value)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => eprintln!("[{}] Failed to save: {}", name, e),
+                        Err(e) => eprintln!("[{}] Failed to save: {}", name, e),
+                    }
+                }
+                for done in flushes {
+                    let _ = done.send(());
+                }
+            }
+        });
+        Self { jobs }
+    }
+
+    pub fn write(&self, value: T) {
+        let _ = self.jobs.send(Job::Write(value));
+    }
+
+    #[allow(unused)] // used by tests
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.jobs.send(Job::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+// a file in a directory of its own, both removed when dropped
+#[allow(unused)] // used by tests
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    #[allow(unused)] // used by tests
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("connect4-{:032x}", rand::random::<u128>()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir.join(name))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Some(dir) = self.0.parent() {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,967 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use futures_util::future::select_all;
+use rand::{Rng, random_bool};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    archive::{ARCHIVE_DIR, Archive, GameRecord, GameResult, unix_time},
+    bot,
+    connect4::{Color, Rules},
+    connection::{ConnRx, Connection, ConnectionUpdate},
+    game::{
+        Game, GameEvent, GameEventTx, GameStatus,
+        clock::TimeControl,
+        message::{FinishedGame, LiveGame, Message, WaitingUser},
+    },
+    ratings::{Outcome, Ratings, RatingsError},
+    tournament::{Tournament, TournamentRx},
+};
+
+use organizer::TournamentGame;
+use queue::Queue;
+use series::{SERIES_LENGTHS, Series};
+
+mod organizer;
+mod queue;
+mod series;
+
+// how long a dropped player has to reconnect before they forfeit
+const GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+const RATINGS_FILE: &str = "ratings.json";
+// players are first paired within this many points of each other, the
+// window widens the longer they wait
+const RATING_WINDOW: u32 = 100;
+const WINDOW_GROWTH_PER_SEC: u32 = 10;
+// waiting players are paired up again this often, as their windows widen
+const MATCHMAKE_INTERVAL: Duration = Duration::from_secs(1);
+// pause between the boards of a series
+const NEXT_GAME_DELAY: Duration = Duration::from_secs(3);
+// how many finished games /lobby observers see
+const RECENT_RESULTS: usize = 10;
+
+// ?room=new opens a room, anything else joins one
+const NEW_ROOM: &str = "new";
+// no 0/O or 1/I, codes get read out loud
+const ROOM_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ROOM_CODE_LEN: usize = 6;
+
+#[derive(Debug, Error)]
+pub enum LobbyError {
+    // fatal
+    #[error("channels were closed prematurely")]
+    ChannelsClosed,
+    #[error("match id is missing")]
+    MissingMatchID,
+    #[error(transparent)]
+    Ratings(#[from] RatingsError),
+}
+
+#[derive(Debug)]
+pub struct Lobby {
+    conn_rx: ConnRx,
+    tournament_rx: TournamentRx,
+    // players waiting for a match, paired in the order they arrived
+    queue: Queue,
+    // players deciding on a rematch, by the game they just finished
+    rematching: HashMap<String, usize>,
+    // private rooms by code, each waiting on its host's invitee
+    rooms: HashMap<String, Waiting>,
+    // challenger to the player they challenged, one at a time each
+    challenges: HashMap<String, String>,
+    playing: HashMap<String, Playing>,
+    // players that dropped out of a game, cancelling the token stops the
+    // grace period
+    away: HashMap<String, CancellationToken>,
+    tournaments: HashMap<usize, Tournament>,
+    tournament_counter: usize,
+    // tournament players waiting on their next opponent
+    entrants: HashMap<String, Waiting>,
+    // /lobby feeds, they're only ever sent to
+    observers: Vec<Connection>,
+    // newest first, at most RECENT_RESULTS
+    results: VecDeque<FinishedGame>,
+
+    over_tx: MatchUpdateTx,
+    over_rx: MatchUpdateRx,
+    game_counter: usize,
+    matches: HashMap<usize, Match>,
+
+    ratings: Ratings,
+    archive: Archive,
+    matchmake_ticker: Interval,
+}
+
+#[derive(Debug)]
+struct Playing {
+    game: usize,
+    resume_token: String,
+}
+
+#[derive(Debug)]
+struct Match {
+    events: GameEventTx,
+    live: LiveGame,
+    private: bool,
+}
+
+#[derive(Debug)]
+struct Waiting {
+    conn: Connection,
+    rules: Rules,
+    time_control: TimeControl,
+    best_of: u32,
+    rating: i32,
+    since: Instant,
+}
+
+#[derive(Debug)]
+struct MatchCandidate {
+    red: Connection,
+    blue: Connection,
+    rules: Rules,
+    time_control: TimeControl,
+    best_of: u32,
+    tournament: Option<TournamentGame>,
+    // played in a private room, kept off the lobby and away from spectators
+    private: bool,
+}
+
+#[derive(Debug)]
+struct MatchOver {
+    id: usize,
+    red: String,
+    blue: String,
+    // games against bots don't count
+    rated: bool,
+    best_of: u32,
+    private: bool,
+    // None if the game never finished
+    outcome: Option<Outcome>,
+    // the players get to ask for a rematch once it's reported
+    rematch_window: bool,
+    tournament: Option<TournamentGame>,
+    // tournament players, back to wait for their next round
+    returning: Vec<Connection>,
+}
+
+impl MatchCandidate {
+    // colors are assigned at random, the game is played the way the
+    // waiting player asked for
+    fn new(w: Waiting, opponent: Connection) -> Self {
+        let (red, blue) = match random_bool(1.0 / 2.0) {
+            true => (w.conn, opponent),
+            false => (opponent, w.conn),
+        };
+        Self {
+            red,
+            blue,
+            rules: w.rules,
+            time_control: w.time_control,
+            best_of: w.best_of,
+            tournament: None,
+            private: false,
+        }
+    }
+}
+
+// what a game task tells the lobby: the result as soon as it's known, then
+// whether the players want to go again
+#[derive(Debug)]
+enum MatchUpdate {
+    Over(MatchOver),
+    RematchOver {
+        id: usize,
+        // None if either player turned it down
+        rematch: Option<Box<MatchCandidate>>,
+    },
+}
+
+type MatchUpdateTx = mpsc::UnboundedSender<MatchUpdate>;
+type MatchUpdateRx = mpsc::UnboundedReceiver<MatchUpdate>;
+
+impl Waiting {
+    fn window(&self) -> u32 {
+        let waited = self.since.elapsed().as_secs() as u32;
+        RATING_WINDOW + WINDOW_GROWTH_PER_SEC * waited
+    }
+}
+
+impl Lobby {
+    pub fn new(conn_rx: ConnRx, tournament_rx: TournamentRx) -> Result<Self, LobbyError> {
+        let (over_tx, over_rx) = mpsc::unbounded_channel::<MatchUpdate>();
+        Ok(Self {
+            conn_rx,
+            tournament_rx,
+            queue: Queue::default(),
+            rematching: HashMap::new(),
+            rooms: HashMap::new(),
+            challenges: HashMap::new(),
+            playing: HashMap::new(),
+            away: HashMap::new(),
+            tournaments: HashMap::new(),
+            tournament_counter: 0,
+            entrants: HashMap::new(),
+            observers: Vec::new(),
+            results: VecDeque::new(),
+
+            over_tx,
+            over_rx,
+            game_counter: 0,
+            matches: HashMap::new(),
+
+            ratings: Ratings::load(RATINGS_FILE)?,
+            archive: Archive::new(ARCHIVE_DIR),
+            matchmake_ticker: tokio::time::interval(MATCHMAKE_INTERVAL),
+        })
+    }
+
+    pub async fn lobby(&mut self) -> Result<(), LobbyError> {
+        tokio::select! {
+            Some(update) = self.over_rx.recv() => match update {
+                MatchUpdate::Over(mo) => self.game_finished(mo).await,
+                MatchUpdate::RematchOver { id, rematch } => {
+                    self.rematch_over(id, rematch);
+                    Ok(())
+                }
+            },
+            Some(cu) = self.conn_rx.recv() => {
+                self.player_connection(cu).await
+            }
+            Some(request) = self.tournament_rx.recv() => {
+                self.tournament_request(request);
+                Ok(())
+            }
+            _ = self.matchmake_ticker.tick() => {
+                self.matchmake_all();
+                Ok(())
+            }
+            (username, msg) = next_message(&mut self.queue) => {
+                self.waiting_message(username, msg);
+                Ok(())
+            }
+            else => Err(LobbyError::ChannelsClosed)
+        }
+    }
+
+    async fn player_connection(&mut self, cu: ConnectionUpdate) -> Result<(), LobbyError> {
+        match cu {
+            ConnectionUpdate::Connected(mut conn) => {
+                let username = conn.username.clone();
+                if self.away.contains_key(&username) && conn.options.resume.is_some() {
+                    self.rejoin(conn)?;
+                    return Ok(());
+                }
+                if self.is_taken(&username) {
+                    let _ = conn.send(Message::RepeatUsername);
+                    println!(
+                        "[Lobby] Player \"{}\" attempted to connect with repeat username",
+                        &username
+                    );
+                    conn.decline();
+                    return Ok(());
+                }
+                // the tournament sets the rules
+                if let Some(id) = conn.options.tournament {
+                    self.join_tournament(conn, id);
+                    return Ok(());
+                }
+                let rules = match conn.options.rules() {
+                    Ok(rules) => rules,
+                    Err(e) => {
+                        let _ = conn.send(Message::InvalidRules(e));
+                        conn.decline();
+                        return Ok(());
+                    }
+                };
+                let room = conn.options.room.clone();
+                if let Some(code) = &room
+                    && code != NEW_ROOM
+                    && !self.rooms.contains_key(code)
+                {
+                    let _ = conn.send(Message::UnknownRoom);
+                    conn.decline();
+                    return Ok(());
+                }
+                let best_of = conn.options.best_of.unwrap_or(1);
+                if !SERIES_LENGTHS.contains(&best_of) {
+                    let _ = conn.send(Message::InvalidSeries);
+                    conn.decline();
+                    return Ok(());
+                }
+                let time_control = conn.options.time_control();
+                let rating = self.ratings.rating(&username);
+                conn.rating = Some(rating);
+                conn.accept();
+                let waiting = Waiting {
+                    conn,
+                    rules,
+                    time_control,
+                    best_of,
+                    rating,
+                    since: Instant::now(),
+                };
+                if let Some(code) = room {
+                    self.private_room(waiting, code);
+                    return Ok(());
+                }
+                if let Some(difficulty) = waiting.conn.options.bot {
+                    println!(
+                        "[Lobby] Player \"{}\" connecting against {} bot",
+                        &username, difficulty
+                    );
+                    let bot = bot::spawn(difficulty);
+                    self.start_match(MatchCandidate::new(waiting, bot));
+                    return Ok(());
+                }
+                println!("[Lobby] Player \"{}\" connecting", &username);
+                self.queue.push(waiting);
+                if let Some(mc) = self.matchmake(&username) {
+                    self.start_match(mc);
+                }
+                self.announce_waiting();
+            }
+            ConnectionUpdate::Watching(game_id, mut conn) => {
+                // private games look the same as ones that don't exist
+                let Some(game) = self.matches.get(&game_id).filter(|m| !m.private) else {
+                    let _ = conn.send(Message::UnknownGame);
+                    conn.decline();
+                    return Ok(());
+                };
+                println!("[Lobby] Spectator joined Game \"{}\"", game_id);
+                conn.accept();
+                let _ = game.events.send(GameEvent::Spectate(conn));
+            }
+            ConnectionUpdate::Observing(mut conn) => {
+                conn.accept();
+                if conn.send(self.lobby_status()).is_ok() {
+                    self.observers.push(conn);
+                }
+            }
+            ConnectionUpdate::Disconnected(username) => {
+                if self.take_waiting(&username).is_some() {
+                    println!("[Lobby] Player \"{}\" disconnected", username);
+                    self.announce_waiting();
+                }
+                if self.entrants.remove(&username).is_some() {
+                    println!("[Lobby] Player \"{}\" left their tournament", username);
+                }
+                self.rooms.retain(|code, w| {
+                    let host_left = w.conn.username == username;
+                    if host_left {
+                        println!("[Lobby] Room \"{}\" closed, its host left", code);
+                    }
+                    !host_left
+                });
+                let game_id = match self.playing.get(&username) {
+                    Some(playing) => playing.game,
+                    None => return Ok(()),
+                };
+                println!(
+                    "[Lobby] Player \"{}\" disconnected from Game \"{}\", waiting for them...",
+                    &username, game_id,
+                );
+                let Some(game) = self.matches.get(&game_id) else {
+                    return Err(LobbyError::MissingMatchID);
+                };
+                let _ = game.events.send(GameEvent::Left(username.clone()));
+
+                let grace = CancellationToken::new();
+                let returned = grace.clone();
+                let events = game.events.clone();
+                let forfeiting = username.clone();
+                tokio::task::spawn(async move {
+                    tokio::select! {
+                        _ = tokio::time::sleep(GRACE_PERIOD) => {
+                            let _ = events.send(GameEvent::Forfeit(forfeiting));
+                        }
+                        _ = returned.cancelled() => {}
+                    }
+                });
+                self.away.insert(username, grace);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn game_finished(&mut self, mut mo: MatchOver) -> Result<(), LobbyError> {
+        println!("[Lobby] Game \"{}\" is over", mo.id);
+        // players who dropped out before the end aren't coming back
+        let returning: Vec<Connection> = mo
+            .returning
+            .drain(..)
+            .filter(|conn| !self.away.contains_key(&conn.username))
+            .collect();
+        // bots are never in playing, so don't kick out a namesake
+        for username in [&mo.red, &mo.blue] {
+            if self.playing.get(username).map(|p| p.game) == Some(mo.id) {
+                let _ = self.playing.remove(username);
+                if let Some(grace) = self.away.remove(username) {
+                    grace.cancel();
+                }
+                // still connected to the game until the window closes
+                if mo.rematch_window {
+                    self.rematching.insert(username.clone(), mo.id);
+                }
+            }
+        }
+        let _ = self.matches.remove(&mo.id);
+
+        if let (true, Some(outcome)) = (mo.rated, &mo.outcome) {
+            self.ratings.record(&mo.red, &mo.blue, outcome);
+            println!(
+                "[Lobby] Ratings now \"{}\": {}, \"{}\": {}",
+                mo.red,
+                self.ratings.rating(&mo.red),
+                mo.blue,
+                self.ratings.rating(&mo.blue)
+            );
+        }
+
+        if let (false, Some(outcome)) = (mo.private, &mo.outcome) {
+            let winner = match outcome {
+                Outcome::Won(winner) => Some(winner.clone()),
+                Outcome::Draw => None,
+            };
+            self.results.push_front(FinishedGame {
+                id: mo.id,
+                red: mo.red.clone(),
+                blue: mo.blue.clone(),
+                winner,
+            });
+            self.results.truncate(RECENT_RESULTS);
+        }
+
+        if let Some(tg) = mo.tournament {
+            for conn in returning {
+                self.return_entrant(conn, tg.tournament);
+            }
+            self.tournament_game_over(tg, mo.outcome.as_ref());
+        }
+        self.announce_lobby();
+        Ok(())
+    }
+
+    // the rematch window of a finished game closed, agreed rematches are
+    // a new match of their own
+    fn rematch_over(&mut self, id: usize, rematch: Option<Box<MatchCandidate>>) {
+        self.rematching.retain(|_, game| *game != id);
+        let Some(mut mc) = rematch else {
+            return;
+        };
+        println!("[Lobby] Rematch for Game \"{}\"", id);
+        for conn in [&mut mc.red, &mut mc.blue] {
+            if !conn.is_local() {
+                conn.rating = Some(self.ratings.rating(&conn.username));
+            }
+        }
+        self.start_match(*mc);
+    }
+
+    // a player who dropped out is back, hand the connection to their game
+    fn rejoin(&mut self, mut conn: Connection) -> Result<(), LobbyError> {
+        let username = conn.username.clone();
+        let Some(playing) = self.playing.get(&username) else {
+            return Err(LobbyError::MissingMatchID);
+        };
+        if conn.options.resume.as_deref() != Some(playing.resume_token.as_str()) {
+            println!(
+                "[Lobby] Player \"{}\" tried to rejoin with a bad token",
+                &username
+            );
+            let _ = conn.send(Message::InvalidResumeToken);
+            conn.decline();
+            return Ok(());
+        }
+        let Some(game) = self.matches.get(&playing.game) else {
+            return Err(LobbyError::MissingMatchID);
+        };
+        println!(
+            "[Lobby] Player \"{}\" rejoined Game \"{}\"",
+            &username, playing.game
+        );
+        if let Some(grace) = self.away.remove(&username) {
+            grace.cancel();
+        }
+        conn.rating = Some(self.ratings.rating(&username));
+        conn.accept();
+        let _ = game.events.send(GameEvent::Rejoined(conn));
+        Ok(())
+    }
+
+    fn is_taken(&self, username: &str) -> bool {
+        self.queue.contains(username)
+            || self.playing.contains_key(username)
+            || self.rematching.contains_key(username)
+            || self.rooms.values().any(|w| w.conn.username == username)
+            || self.entrants.contains_key(username)
+    }
+
+    // the host gets a code to share, whoever joins with it plays them
+    // under the host's rules
+    fn private_room(&mut self, waiting: Waiting, code: String) {
+        let username = waiting.conn.username.clone();
+        if code != NEW_ROOM {
+            let Some(host) = self.rooms.remove(&code) else {
+                return;
+            };
+            println!("[Lobby] Player \"{}\" joined room \"{}\"", &username, &code);
+            let mut mc = MatchCandidate::new(host, waiting.conn);
+            mc.private = true;
+            self.start_match(mc);
+            return;
+        }
+
+        let mut rng = rand::rng();
+        let code = loop {
+            let code: String = (0..ROOM_CODE_LEN)
+                .map(|_| ROOM_CODE_CHARS[rng.random_range(0..ROOM_CODE_CHARS.len())] as char)
+                .collect();
+            if !self.rooms.contains_key(&code) {
+                break code;
+            }
+        };
+        println!("[Lobby] Player \"{}\" opened room \"{}\"", &username, &code);
+        let _ = waiting
+            .conn
+            .send(Message::RoomCreated { code: code.clone() });
+        self.rooms.insert(code, waiting);
+    }
+
+    // windows only ever widen, so longest waiting players get first pick
+    fn matchmake_all(&mut self) {
+        let usernames: Vec<String> = self.queue.iter().map(|w| w.conn.username.clone()).collect();
+        let mut matched = false;
+        for username in usernames {
+            if let Some(mc) = self.matchmake(&username) {
+                self.start_match(mc);
+                matched = true;
+            }
+        }
+        if matched {
+            self.announce_waiting();
+        }
+    }
+
+    // messages from players still waiting for a match
+    fn waiting_message(&mut self, username: String, msg: Option<Message>) {
+        let Some(msg) = msg else {
+            // their socket closed, the disconnect isn't far behind
+            if self.take_waiting(&username).is_some() {
+                println!("[Lobby] Player \"{}\" disconnected", username);
+                self.announce_waiting();
+            }
+            return;
+        };
+        let reply = match msg {
+            Message::Challenge { opponent } => self.challenge(&username, opponent),
+            Message::AcceptChallenge { from } => self.accept_challenge(&username, from),
+            Message::DeclineChallenge { from } => {
+                if self.challenges.get(&from) == Some(&username) {
+                    self.challenges.remove(&from);
+                    self.send_waiting(
+                        &from,
+                        Message::ChallengeDeclined {
+                            by: username.clone(),
+                        },
+                    );
+                }
+                None
+            }
+            _ => Some(Message::InvalidMessage),
+        };
+        if let Some(reply) = reply {
+            self.send_waiting(&username, reply);
+        }
+    }
+
+    fn challenge(&mut self, username: &str, opponent: String) -> Option<Message> {
+        if opponent == username {
+            return Some(Message::UnknownOpponent);
+        }
+        let (Some(challenger), Some(_)) = (self.queue.get(username), self.queue.get(&opponent))
+        else {
+            return Some(Message::UnknownOpponent);
+        };
+        let challenged = Message::Challenged {
+            from: username.to_string(),
+            rating: challenger.rating,
+            rules: challenger.rules,
+            time_control: challenger.time_control,
+            best_of: challenger.best_of,
+        };
+        println!(
+            "[Lobby] Player \"{}\" challenged \"{}\"",
+            username, &opponent
+        );
+        self.send_waiting(&opponent, challenged);
+        // a new challenge replaces the last one
+        self.challenges.insert(username.to_string(), opponent);
+        None
+    }
+
+    // the game is played under the challenger's rules
+    fn accept_challenge(&mut self, username: &str, from: String) -> Option<Message> {
+        if self.challenges.get(&from).map(String::as_str) != Some(username)
+            || !self.queue.contains(&from)
+            || !self.queue.contains(username)
+        {
+            return Some(Message::UnknownOpponent);
+        }
+        let (Some(challenger), Some(accepter)) =
+            (self.take_matched(&from), self.take_matched(username))
+        else {
+            unreachable!(); // both were just found in the queue
+        };
+        self.start_match(MatchCandidate::new(challenger, accepter.conn));
+        self.announce_waiting();
+        None
+    }
+
+    fn send_waiting(&self, username: &str, msg: Message) {
+        if let Some(waiting) = self.queue.get(username) {
+            let _ = waiting.conn.send(msg);
+        }
+    }
+
+    // everyone waiting gets the full list and their place in the queue
+    // whenever it changes
+    fn announce_waiting(&mut self) {
+        let users = self.waiting_users();
+        for (index, waiting) in self.queue.iter().enumerate() {
+            let _ = waiting.conn.send(Message::WaitingUsers {
+                users: users.clone(),
+            });
+            let estimated_wait = self.queue.estimated_wait(waiting);
+            let _ = waiting.conn.send(Message::Queued {
+                position: index + 1,
+                estimated_wait: estimated_wait.map(|wait| wait.as_secs()),
+            });
+        }
+        self.announce_lobby();
+    }
+
+    fn waiting_users(&self) -> Vec<WaitingUser> {
+        self.queue
+            .iter()
+            .map(|w| WaitingUser {
+                username: w.conn.username.clone(),
+                rating: w.rating,
+                rules: w.rules,
+                time_control: w.time_control,
+                best_of: w.best_of,
+            })
+            .collect()
+    }
+
+    // observers that have gone away are dropped on the next update
+    fn announce_lobby(&mut self) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let status = self.lobby_status();
+        self.observers
+            .retain(|observer| observer.send(status.clone()).is_ok());
+    }
+
+    fn lobby_status(&self) -> Message {
+        let mut games: Vec<LiveGame> = self
+            .matches
+            .values()
+            .filter(|m| !m.private)
+            .map(|m| m.live.clone())
+            .collect();
+        games.sort_by_key(|game| game.id);
+        Message::LobbyStatus {
+            waiting: self.waiting_users(),
+            games,
+            results: self.results.iter().cloned().collect(),
+        }
+    }
+
+    // takes the player out of the queue along with any challenges
+    // involving them
+    fn take_waiting(&mut self, username: &str) -> Option<Waiting> {
+        let waiting = self.queue.remove(username)?;
+        self.challenges
+            .retain(|challenger, opponent| challenger != username && opponent != username);
+        Some(waiting)
+    }
+
+    // like take_waiting, but the wait counts towards the queue's metrics
+    fn take_matched(&mut self, username: &str) -> Option<Waiting> {
+        let waiting = self.take_waiting(username)?;
+        self.queue.record_wait(&waiting);
+        let waits = self.queue.waits();
+        println!(
+            "[Lobby] Player \"{}\" matched after {:.1}s (average {:.1}s, longest {:.1}s over {} matches)",
+            username,
+            waiting.since.elapsed().as_secs_f32(),
+            waits.average().unwrap_or_default().as_secs_f32(),
+            waits.longest.as_secs_f32(),
+            waits.matched,
+        );
+        Some(waiting)
+    }
+
+    // pairs the player with whoever has waited longest out of the opponents
+    // either of them is willing to play
+    fn matchmake(&mut self, username: &str) -> Option<MatchCandidate> {
+        let waiting = self.queue.get(username)?;
+        if waiting.conn.options.challenges_only {
+            return None;
+        }
+        let (rules, time_control, best_of) = (waiting.rules, waiting.time_control, waiting.best_of);
+        let opponent = self
+            .queue
+            .iter()
+            .find(|w| {
+                w.conn.username != username
+                    && !w.conn.options.challenges_only
+                    && w.rules == rules
+                    && w.time_control == time_control
+                    && w.best_of == best_of
+                    && waiting.rating.abs_diff(w.rating) <= w.window().max(waiting.window())
+            })
+            .map(|w| w.conn.username.clone())?;
+
+        // the opponent is ahead in the queue unless they only just arrived
+        let w2 = self.take_matched(&opponent);
+        let w1 = self.take_matched(username);
+        let (Some(w1), Some(w2)) = (w1, w2) else {
+            unreachable!(); // we should panic cause this is impossible
+        };
+
+        Some(MatchCandidate::new(w1, w2.conn))
+    }
+
+    fn start_match(&mut self, mc: MatchCandidate) -> usize {
+        let id = self.game_counter;
+        let (events_tx, events_rx) = mpsc::unbounded_channel::<GameEvent>();
+        let red_username = mc.red.username.clone();
+        let blue_username = mc.blue.username.clone();
+        let red_local = mc.red.is_local();
+        let blue_local = mc.blue.is_local();
+        let rated = !red_local && !blue_local;
+
+        // Game manages connections
+        // MatchOver is just the msg used by the game thread to signal lobby thread
+        let game = Game::new(id, events_rx, mc.rules, mc.time_control, mc.red, mc.blue);
+
+        // bots can't reconnect or collide, only track players
+        for (color, username, local) in [
+            (Color::Red, &red_username, red_local),
+            (Color::Blue, &blue_username, blue_local),
+        ] {
+            if !local {
+                let playing = Playing {
+                    game: id,
+                    resume_token: game.resume_token(color).to_string(),
+                };
+                self.playing.insert(username.clone(), playing);
+            }
+        }
+        let mo = MatchOver {
+            id,
+            red: red_username.clone(),
+            blue: blue_username.clone(),
+            rated,
+            best_of: mc.best_of,
+            private: mc.private,
+            outcome: None,
+            rematch_window: false,
+            tournament: mc.tournament,
+            returning: Vec::new(),
+        };
+
+        let game_match = Match {
+            events: events_tx,
+            live: LiveGame {
+                id,
+                red: red_username.clone(),
+                blue: blue_username.clone(),
+                best_of: mc.best_of,
+            },
+            private: mc.private,
+        };
+        self.matches.insert(id, game_match);
+        self.game_counter += 1;
+
+        let over_tx = self.over_tx.clone();
+        let archive = self.archive.clone();
+        tokio::task::spawn(async move { gameplay(game, mo, over_tx, archive).await });
+
+        println!(
+            "[Lobby] Starting Game \"{}\"; Red: \"{}\", Blue: \"{}\"",
+            id, red_username, blue_username
+        );
+        self.announce_lobby();
+        id
+    }
+}
+
+// we need a channel to back feed the lobby with Gameplay Results
+async fn gameplay(mut game: Game, mut mo: MatchOver, over_tx: MatchUpdateTx, archive: Archive) {
+    if let Err(e) = game.game_start().await {
+        println!("[Game {}] Failed to start, ending game: {}", game.id(), e);
+        game.game_over();
+        let _ = over_tx.send(MatchUpdate::Over(mo));
+        return;
+    }
+
+    // every board of a series is played out on the same Game
+    let mut series = Series::new(mo.best_of, &mo.red, &mo.blue);
+    let finished = loop {
+        let started_at = unix_time();
+        let result = play_out(&mut game).await;
+        let record = GameRecord {
+            id: format!("{}-{}-{}", started_at, game.id(), series.played()),
+            red: game.username(Color::Red),
+            blue: game.username(Color::Blue),
+            rules: game.board().rules(),
+            time_control: game.time_control(),
+            moves: game.board().history().to_vec(),
+            notation: game.board().to_moves().ok().map(|moves| moves.to_string()),
+            result: result.clone(),
+            started_at,
+            ended_at: unix_time(),
+        };
+        let saving = archive.clone();
+        match tokio::task::spawn_blocking(move || saving.save(&record)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => println!("[Game {}] Failed to archive game: {}", game.id(), e),
+            Err(e) => println!("[Game {}] Failed to archive game: {}", game.id(), e),
+        }
+
+        let outcome = match result {
+            GameResult::Won { winner }
+            | GameResult::TimedOut { winner }
+            | GameResult::Resigned { winner } => Outcome::Won(winner),
+            GameResult::Stalemate | GameResult::DrawAgreed => Outcome::Draw,
+            // walking out loses the whole series, not just this board
+            GameResult::Forfeited { winner } => {
+                mo.outcome = Some(Outcome::Won(winner));
+                break true;
+            }
+            GameResult::Abandoned => break false,
+        };
+        if series.is_single_game() {
+            mo.outcome = Some(outcome);
+            break true;
+        }
+        series.record(&outcome);
+        let _ = game.broadcast(series.score());
+        if series.is_over() {
+            println!("[Game {}] Series over", game.id());
+            let _ = game.broadcast(series.result());
+            mo.outcome = Some(series.outcome());
+            break true;
+        }
+
+        // a moment to take in the last board before the next one
+        tokio::time::sleep(NEXT_GAME_DELAY).await;
+        if let Err(e) = game.next_board().await {
+            println!("[Game {}] Failed to start next game: {}", game.id(), e);
+            break false;
+        }
+    };
+
+    if finished && mo.tournament.is_some() {
+        let (red, blue) = game.into_players();
+        mo.returning = vec![red, blue];
+        let _ = over_tx.send(MatchUpdate::Over(mo));
+        return;
+    }
+    if !finished {
+        let _ = over_tx.send(MatchUpdate::Over(mo));
+        game.game_over();
+        return;
+    }
+
+    // the result counts now, not once the players make up their minds
+    let (id, best_of, private) = (mo.id, mo.best_of, mo.private);
+    mo.rematch_window = true;
+    let _ = over_tx.send(MatchUpdate::Over(mo));
+
+    let rematch = match game.rematch().await {
+        true => {
+            let (rules, time_control) = (game.board().rules(), game.time_control());
+            // colors swap, so each player gets to move first in turn
+            let (red, blue) = game.into_players();
+            Some(Box::new(MatchCandidate {
+                red: blue,
+                blue: red,
+                rules,
+                time_control,
+                best_of,
+                tournament: None,
+                private,
+            }))
+        }
+        false => {
+            game.game_over();
+            None
+        }
+    };
+    let _ = over_tx.send(MatchUpdate::RematchOver { id, rematch });
+}
+
+// plays a single board to the end
+async fn play_out(game: &mut Game) -> GameResult {
+    loop {
+        match game.play().await {
+            Ok(status) => match status {
+                GameStatus::Playing => {}
+                GameStatus::GameWon(winner) => {
+                    println!("[Game {}] \"{}\" won", game.id(), winner);
+                    return GameResult::Won { winner };
+                }
+                GameStatus::Stalemate => {
+                    println!("[Game {}] Ended in stalemate", game.id());
+                    return GameResult::Stalemate;
+                }
+                GameStatus::TimedOut(winner) => {
+                    println!("[Game {}] \"{}\" won on time", game.id(), winner);
+                    return GameResult::TimedOut { winner };
+                }
+                GameStatus::Resigned(winner) => {
+                    println!("[Game {}] \"{}\" won by resignation", game.id(), winner);
+                    return GameResult::Resigned { winner };
+                }
+                GameStatus::Forfeited(winner) => {
+                    println!("[Game {}] \"{}\" won by forfeit", game.id(), winner);
+                    return GameResult::Forfeited { winner };
+                }
+                GameStatus::DrawAgreed => {
+                    println!("[Game {}] Ended in an agreed draw", game.id());
+                    return GameResult::DrawAgreed;
+                }
+            },
+            Err(e) => {
+                println!("[Game {}] Error: {}", game.id(), e);
+                return GameResult::Abandoned;
+            }
+        }
+    }
+}
+
+// resolves with the next message any waiting player sends, None once their
+// connection is gone
+async fn next_message(queue: &mut Queue) -> (String, Option<Message>) {
+    if queue.is_empty() {
+        return std::future::pending().await;
+    }
+    let reads = queue.iter_mut().map(|waiting| {
+        Box::pin(async move {
+            let msg = waiting.conn.recv().await;
+            (waiting.conn.username.clone(), msg)
+        })
+    });
+    select_all(reads).await.0
+}
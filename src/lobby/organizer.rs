@@ -0,0 +1,214 @@
+use tokio::time::Instant;
+
+use super::{Lobby, MatchCandidate, SERIES_LENGTHS, Waiting};
+use crate::{
+    connection::Connection,
+    game::message::Message,
+    ratings::Outcome,
+    tournament::{Tournament, TournamentError, TournamentRequest, TournamentSpec},
+};
+
+// tournaments are kept in memory, finished ones make room for new ones
+const MAX_TOURNAMENTS: usize = 20;
+
+// which pairing a match is playing, so its result finds its way back
+#[derive(Clone, Copy, Debug)]
+pub(super) struct TournamentGame {
+    pub(super) tournament: usize,
+    pub(super) round: usize,
+    pub(super) pairing: usize,
+}
+
+impl Lobby {
+    pub(super) fn tournament_request(&mut self, request: TournamentRequest) {
+        match request {
+            TournamentRequest::Create(spec, reply) => {
+                let _ = reply.send(self.create_tournament(spec));
+            }
+            TournamentRequest::Get(id, reply) => {
+                let _ = reply.send(self.tournaments.get(&id).cloned());
+            }
+            TournamentRequest::List(reply) => {
+                let mut tournaments: Vec<Tournament> = self.tournaments.values().cloned().collect();
+                tournaments.sort_by_key(|t| t.id);
+                let _ = reply.send(tournaments);
+            }
+        }
+    }
+
+    fn create_tournament(&mut self, spec: TournamentSpec) -> Result<usize, TournamentError> {
+        let options = spec.options();
+        let rules = options.rules()?;
+        let best_of = options.best_of.unwrap_or(1);
+        if !SERIES_LENGTHS.contains(&best_of) {
+            return Err(TournamentError::InvalidSeries);
+        }
+        let time_control = options.time_control();
+        let id = self.tournament_counter;
+        let tournament = Tournament::new(id, spec, rules, time_control, best_of)?;
+        if self.tournaments.len() >= MAX_TOURNAMENTS {
+            let oldest_finished = self
+                .tournaments
+                .values()
+                .filter(|t| t.finished)
+                .map(|t| t.id)
+                .min();
+            let Some(oldest) = oldest_finished else {
+                return Err(TournamentError::TooMany);
+            };
+            self.tournaments.remove(&oldest);
+        }
+        println!(
+            "[Lobby] Tournament \"{}\" created with {} players",
+            id,
+            tournament.players.len()
+        );
+        self.tournaments.insert(id, tournament);
+        self.tournament_counter += 1;
+        Ok(id)
+    }
+
+    // a player on the roster connected, they play as soon as their opponent
+    // for the round is here too
+    pub(super) fn join_tournament(&mut self, mut conn: Connection, id: usize) {
+        let username = conn.username.clone();
+        let Some(tournament) = self.tournaments.get(&id).filter(|t| t.is_player(&username)) else {
+            let _ = conn.send(Message::UnknownTournament);
+            conn.decline();
+            return;
+        };
+        if tournament.finished {
+            let _ = conn.send(Message::TournamentOver {
+                tournament: id,
+                winner: tournament.winner.clone(),
+            });
+            conn.decline();
+            return;
+        }
+        let rating = self.ratings.rating(&username);
+        conn.rating = Some(rating);
+        conn.accept();
+        println!(
+            "[Lobby] Player \"{}\" connecting for Tournament \"{}\"",
+            &username, id
+        );
+        let waiting = Waiting {
+            conn,
+            rules: tournament.rules,
+            time_control: tournament.time_control,
+            best_of: tournament.best_of,
+            rating,
+            since: Instant::now(),
+        };
+        self.entrants.insert(username, waiting);
+        self.start_tournament_games(id);
+    }
+
+    // back from a finished game to wait for the next round
+    pub(super) fn return_entrant(&mut self, mut conn: Connection, id: usize) {
+        let Some(tournament) = self.tournaments.get(&id) else {
+            return;
+        };
+        let rating = self.ratings.rating(&conn.username);
+        conn.rating = Some(rating);
+        let waiting = Waiting {
+            rules: tournament.rules,
+            time_control: tournament.time_control,
+            best_of: tournament.best_of,
+            rating,
+            since: Instant::now(),
+            conn,
+        };
+        self.entrants.insert(waiting.conn.username.clone(), waiting);
+    }
+
+    pub(super) fn tournament_game_over(&mut self, tg: TournamentGame, outcome: Option<&Outcome>) {
+        let Some(tournament) = self.tournaments.get_mut(&tg.tournament) else {
+            return;
+        };
+        tournament.record(tg.round, tg.pairing, outcome);
+        if tournament.finished {
+            println!(
+                "[Lobby] Tournament \"{}\" is over, winner: {:?}",
+                tg.tournament, tournament.winner
+            );
+        }
+        self.start_tournament_games(tg.tournament);
+    }
+
+    // starts every pairing of the current round whose players are both
+    // here, everyone else in the tournament hears where things stand
+    fn start_tournament_games(&mut self, id: usize) {
+        let Some(tournament) = self.tournaments.get(&id) else {
+            return;
+        };
+        let round = tournament.round;
+        let here = |username: &str| {
+            self.entrants
+                .get(username)
+                .is_some_and(|w| w.conn.options.tournament == Some(id))
+        };
+        let ready: Vec<(usize, String, String)> = tournament
+            .pending()
+            .into_iter()
+            .filter_map(|index| {
+                let pairing = tournament.pairing(round, index)?;
+                let blue = pairing.blue.as_ref()?;
+                (here(&pairing.red) && here(blue))
+                    .then(|| (index, pairing.red.clone(), blue.clone()))
+            })
+            .collect();
+
+        for (index, red, blue) in ready {
+            let (Some(red), Some(blue)) = (self.entrants.remove(&red), self.entrants.remove(&blue))
+            else {
+                unreachable!(); // both were just found in entrants
+            };
+            let game = self.start_match(MatchCandidate {
+                red: red.conn,
+                blue: blue.conn,
+                rules: red.rules,
+                time_control: red.time_control,
+                best_of: red.best_of,
+                tournament: Some(TournamentGame {
+                    tournament: id,
+                    round,
+                    pairing: index,
+                }),
+                private: false,
+            });
+            if let Some(tournament) = self.tournaments.get_mut(&id) {
+                tournament.started(index, game);
+            }
+        }
+
+        let Some(tournament) = self.tournaments.get(&id) else {
+            return;
+        };
+        if tournament.finished {
+            let winner = tournament.winner.clone();
+            self.entrants.retain(|_, w| {
+                if w.conn.options.tournament != Some(id) {
+                    return true;
+                }
+                let _ = w.conn.send(Message::TournamentOver {
+                    tournament: id,
+                    winner: winner.clone(),
+                });
+                w.conn.close();
+                false
+            });
+            return;
+        }
+        for waiting in self
+            .entrants
+            .values()
+            .filter(|w| w.conn.options.tournament == Some(id))
+        {
+            let _ = waiting.conn.send(Message::TournamentWaiting {
+                tournament: id,
+                round: tournament.round,
+            });
+        }
+    }
+}
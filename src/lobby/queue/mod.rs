@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::Waiting;
+
+mod test;
+
+// only the most recent waits feed the estimate
+const RECENT_WAITS: usize = 50;
+
+// players waiting for a match, longest waiting first
+#[derive(Debug, Default)]
+pub(super) struct Queue {
+    players: VecDeque<Waiting>,
+    waits: WaitTimes,
+}
+
+// how long matched players spent in the queue
+#[derive(Debug, Default)]
+pub(super) struct WaitTimes {
+    recent: VecDeque<Duration>,
+    pub matched: u64,
+    pub longest: Duration,
+}
+
+impl Queue {
+    pub(super) fn push(&mut self, waiting: Waiting) {
+        self.players.push_back(waiting);
+    }
+
+    pub(super) fn get(&self, username: &str) -> Option<&Waiting> {
+        self.players.iter().find(|w| w.conn.username == username)
+    }
+
+    pub(super) fn contains(&self, username: &str) -> bool {
+        self.get(username).is_some()
+    }
+
+    pub(super) fn remove(&mut self, username: &str) -> Option<Waiting> {
+        let index = self
+            .players
+            .iter()
+            .position(|w| w.conn.username == username)?;
+        self.players.remove(index)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Waiting> {
+        self.players.iter()
+    }
+
+    pub(super) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Waiting> {
+        self.players.iter_mut()
+    }
+
+    pub(super) fn waits(&self) -> &WaitTimes {
+        &self.waits
+    }
+
+    pub(super) fn record_wait(&mut self, waiting: &Waiting) {
+        self.waits.record(waiting.since.elapsed());
+    }
+
+    // recent average less what they've already waited, None until someone
+    // has been matched
+    pub(super) fn estimated_wait(&self, waiting: &Waiting) -> Option<Duration> {
+        let average = self.waits.average()?;
+        Some(average.saturating_sub(waiting.since.elapsed()))
+    }
+}
+
+impl WaitTimes {
+    fn record(&mut self, wait: Duration) {
+        if self.recent.len() == RECENT_WAITS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(wait);
+        self.matched += 1;
+        self.longest = self.longest.max(wait);
+    }
+
+    pub(super) fn average(&self) -> Option<Duration> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let total: Duration = self.recent.iter().sum();
+        Some(total / self.recent.len() as u32)
+    }
+}
@@ -0,0 +1,108 @@
+#[allow(unused_imports)]
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use tokio::sync::mpsc;
+#[allow(unused_imports)]
+use tokio::time::Instant;
+
+#[allow(unused_imports)]
+use crate::connect4::Rules;
+#[allow(unused_imports)]
+use crate::connection::Connection;
+#[allow(unused_imports)]
+use crate::game::clock::TimeControl;
+#[allow(unused_imports)]
+use crate::lobby::{
+    Lobby, Waiting,
+    queue::{Queue, WaitTimes},
+};
+
+// a player who joined the queue just now
+#[allow(unused)] // used by tests
+fn waiting(username: &str, rating: i32) -> Waiting {
+    let (conn, _peer) = Connection::local(username.to_string());
+    Waiting {
+        conn,
+        rules: Rules::new(7, 6, 4).unwrap(),
+        time_control: TimeControl::default(),
+        best_of: 1,
+        rating,
+        since: Instant::now(),
+    }
+}
+
+// who the player was paired with, if anyone
+#[allow(unused)] // used by tests
+fn opponent(lobby: &mut Lobby, username: &str) -> Option<String> {
+    let mc = lobby.matchmake(username)?;
+    let (red, blue) = (mc.red.username, mc.blue.username);
+    Some(if red == username { blue } else { red })
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_pairing_order() {
+    let (_conn_tx, conn_rx) = mpsc::unbounded_channel();
+    let (_tournament_tx, tournament_rx) = mpsc::unbounded_channel();
+    let mut lobby = Lobby::new(conn_rx, tournament_rx).unwrap();
+    for (username, rating) in [
+        ("alice", 1500),
+        ("bob", 1700),
+        ("carol", 1550),
+        ("dave", 1520),
+    ] {
+        lobby.queue.push(waiting(username, rating));
+    }
+
+    // bob is out of alice's window, carol has waited longer than dave
+    assert_eq!(opponent(&mut lobby, "alice").as_deref(), Some("carol"));
+    assert!(!lobby.queue.contains("alice") && !lobby.queue.contains("carol"));
+    assert_eq!(opponent(&mut lobby, "bob"), None);
+
+    // 180 points apart, in range once the windows have grown to 200
+    tokio::time::advance(Duration::from_secs(7)).await;
+    assert_eq!(opponent(&mut lobby, "bob"), None);
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert_eq!(opponent(&mut lobby, "bob").as_deref(), Some("dave"));
+    assert!(lobby.queue.is_empty());
+}
+
+#[test]
+fn test_wait_average() {
+    let mut waits = WaitTimes::default();
+    assert_eq!(waits.average(), None);
+    waits.record(Duration::from_secs(2));
+    waits.record(Duration::from_secs(6));
+    assert_eq!(waits.average(), Some(Duration::from_secs(4)));
+    assert_eq!(waits.longest, Duration::from_secs(6));
+
+    // only the most recent waits count towards the average
+    for _ in 0..49 {
+        waits.record(Duration::from_secs(1));
+    }
+    assert_eq!(waits.average(), Some(Duration::from_millis(1_100)));
+    waits.record(Duration::from_secs(1));
+    assert_eq!(waits.average(), Some(Duration::from_secs(1)));
+    // the totals still cover everyone
+    assert_eq!(waits.matched, 52);
+    assert_eq!(waits.longest, Duration::from_secs(6));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_estimated_wait() {
+    let mut queue = Queue::default();
+    let early = waiting("alice", 1500);
+    // nobody has been matched, there's nothing to go on
+    assert_eq!(queue.estimated_wait(&early), None);
+
+    tokio::time::advance(Duration::from_secs(10)).await;
+    queue.record_wait(&early);
+    let late = waiting("bob", 1500);
+    assert_eq!(queue.estimated_wait(&late), Some(Duration::from_secs(10)));
+
+    // time already spent waiting comes off, never below nothing
+    tokio::time::advance(Duration::from_secs(4)).await;
+    assert_eq!(queue.estimated_wait(&late), Some(Duration::from_secs(6)));
+    tokio::time::advance(Duration::from_secs(20)).await;
+    assert_eq!(queue.estimated_wait(&late), Some(Duration::ZERO));
+}
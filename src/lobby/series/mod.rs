@@ -0,0 +1,81 @@
+use crate::game::message::Message;
+use crate::ratings::Outcome;
+
+mod test;
+
+// how many games a match can be the best of, a single game is a series of 1
+pub(super) const SERIES_LENGTHS: [u32; 4] = [1, 3, 5, 7];
+
+// the running score of a best-of-N match, draws don't count towards either
+// player
+#[derive(Debug)]
+pub(super) struct Series {
+    best_of: u32,
+    played: u32,
+    players: [String; 2],
+    wins: [u32; 2],
+}
+
+impl Series {
+    pub(super) fn new(best_of: u32, first: &str, second: &str) -> Self {
+        Self {
+            best_of,
+            played: 0,
+            players: [first.to_string(), second.to_string()],
+            wins: [0, 0],
+        }
+    }
+
+    pub(super) fn played(&self) -> u32 {
+        self.played
+    }
+
+    pub(super) fn is_single_game(&self) -> bool {
+        self.best_of == 1
+    }
+
+    pub(super) fn record(&mut self, outcome: &Outcome) {
+        self.played += 1;
+        if let Outcome::Won(winner) = outcome
+            && let Some(index) = self.players.iter().position(|p| p == winner)
+        {
+            self.wins[index] += 1;
+        }
+    }
+
+    // over once nobody can catch up, or every game has been played
+    pub(super) fn is_over(&self) -> bool {
+        let needed = self.best_of / 2 + 1;
+        self.played >= self.best_of || self.wins.iter().any(|&w| w >= needed)
+    }
+
+    // the series as a whole, a tie is a draw
+    pub(super) fn outcome(&self) -> Outcome {
+        match self.wins[0].cmp(&self.wins[1]) {
+            std::cmp::Ordering::Greater => Outcome::Won(self.players[0].clone()),
+            std::cmp::Ordering::Less => Outcome::Won(self.players[1].clone()),
+            std::cmp::Ordering::Equal => Outcome::Draw,
+        }
+    }
+
+    pub(super) fn score(&self) -> Message {
+        Message::SeriesScore {
+            best_of: self.best_of,
+            played: self.played,
+            players: self.players.clone(),
+            wins: self.wins,
+        }
+    }
+
+    pub(super) fn result(&self) -> Message {
+        let winner = match self.outcome() {
+            Outcome::Won(winner) => Some(winner),
+            Outcome::Draw => None,
+        };
+        Message::SeriesOver {
+            winner,
+            players: self.players.clone(),
+            wins: self.wins,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+#[allow(unused_imports)]
+use tokio::sync::mpsc;
+
+#[allow(unused_imports)]
+use crate::Connection;
+#[allow(unused_imports)]
+use crate::connect4::{Color, Rules};
+#[allow(unused_imports)]
+use crate::game::{Game, clock::TimeControl};
+#[allow(unused_imports)]
+use crate::lobby::series::Series;
+#[allow(unused_imports)]
+use crate::ratings::Outcome;
+
+#[allow(unused)] // used by tests
+fn won(username: &str) -> Outcome {
+    Outcome::Won(username.to_string())
+}
+
+#[test]
+fn test_series_clinched_early() {
+    let mut series = Series::new(5, "alice", "bob");
+    series.record(&won("alice"));
+    series.record(&won("bob"));
+    series.record(&won("alice"));
+    assert!(!series.is_over());
+    // bob can't reach 3 wins with one game left to play
+    series.record(&won("alice"));
+    assert!(series.is_over());
+    assert_eq!(series.played(), 4);
+    assert!(matches!(series.outcome(), Outcome::Won(w) if w == "alice"));
+}
+
+#[test]
+fn test_series_draws_dont_count() {
+    let mut series = Series::new(3, "alice", "bob");
+    series.record(&Outcome::Draw);
+    series.record(&won("bob"));
+    // a draw took up a game but bob still needs a second win
+    assert!(!series.is_over());
+    assert_eq!(series.wins, [0, 1]);
+
+    series.record(&won("alice"));
+    assert!(series.is_over());
+    assert!(matches!(series.outcome(), Outcome::Draw));
+
+    let mut series = Series::new(3, "alice", "bob");
+    series.record(&Outcome::Draw);
+    series.record(&Outcome::Draw);
+    series.record(&won("bob"));
+    assert!(matches!(series.outcome(), Outcome::Won(w) if w == "bob"));
+}
+
+#[tokio::test]
+async fn test_series_colors_swap() {
+    let (red, _red_peer) = Connection::local("alice".to_string());
+    let (blue, _blue_peer) = Connection::local("bob".to_string());
+    let (_events_tx, events_rx) = mpsc::unbounded_channel();
+    let rules = Rules::new(7, 6, 4).unwrap();
+    let mut game = Game::new(0, events_rx, rules, TimeControl::default(), red, blue);
+    game.game_start().await.unwrap();
+
+    let mut series = Series::new(3, "alice", "bob");
+    series.record(&won(&game.username(Color::Red)));
+    // the other player moves first on the next board
+    game.next_board().await.unwrap();
+    assert_eq!(game.username(Color::Red), "bob");
+    assert_eq!(game.username(Color::Blue), "alice");
+
+    // wins follow the player, not the color
+    series.record(&won(&game.username(Color::Blue)));
+    assert!(series.is_over());
+    assert_eq!(series.wins, [2, 0]);
+    assert!(matches!(series.outcome(), Outcome::Won(w) if w == "alice"));
+}
@@ -1,20 +1,38 @@
-use tokio::sync::mpsc;
-use warp::{Filter, ws};
+use std::sync::Arc;
+
+use serde_json::json;
+use tokio::sync::{Semaphore, mpsc, oneshot};
+use warp::{Filter, Reply, http::StatusCode, ws};
 
 use crate::{
-    connection::{ConnTx, Connection, ConnectionUpdate},
+    accounts::{
+        ACCOUNTS_FILE, Accounts, AccountsError, Attempts, Credentials, HASHING_SLOTS, SessionQuery,
+    },
+    archive::{ARCHIVE_DIR, Archive, GamesQuery},
+    connection::{ConnTx, Connection, ConnectionUpdate, PlayOptions},
     lobby::Lobby,
+    tournament::{TournamentRequest, TournamentSpec, TournamentTx},
 };
 
+mod accounts;
+mod archive;
+mod bot;
 mod connect4;
 mod connection;
 mod game;
+mod json_file;
 mod lobby;
+mod ratings;
+mod tournament;
 
 #[tokio::main]
 async fn main() {
     let (ic_tx, ic_rx) = mpsc::unbounded_channel::<ConnectionUpdate>();
-    let mut lobby = Lobby::new(ic_rx);
+    let (tournament_tx, tournament_rx) = mpsc::unbounded_channel::<TournamentRequest>();
+    let mut lobby = match Lobby::new(ic_rx, tournament_rx) {
+        Ok(lobby) => lobby,
+        Err(e) => panic!("{}", e),
+    };
 
     tokio::task::spawn(async move {
         loop {
@@ -25,22 +43,266 @@ async fn main() {
         }
     });
 
-    routes(ic_tx).await;
+    let accounts = match Accounts::load(ACCOUNTS_FILE) {
+        Ok(accounts) => Arc::new(accounts),
+        Err(e) => panic!("{}", e),
+    };
+
+    routes(ic_tx, tournament_tx, accounts).await;
 }
 
-async fn routes(ic_tx: ConnTx) {
+async fn routes(ic_tx: ConnTx, tournament_tx: TournamentTx, accounts: Arc<Accounts>) {
     let ic_filter = warp::any().map(move || ic_tx.clone());
+    let accounts_filter = warp::any().map(move || accounts.clone());
+    let attempts = Arc::new(Attempts::default());
+    let hashing = Arc::new(Semaphore::new(HASHING_SLOTS));
+    let hashing_filter = warp::any().map(move || Hashing {
+        attempts: attempts.clone(),
+        slots: hashing.clone(),
+    });
+    let tournament_filter = warp::any().map(move || tournament_tx.clone());
 
     let static_files = warp::get().and(warp::fs::dir("static"));
 
+    // players have to be logged in as the name they play under
     let ws_play = warp::path!("play" / String)
+        .and(warp::query::<PlayOptions>())
+        .and(warp::query::<SessionQuery>())
+        .and(warp::ws())
+        .and(ic_filter.clone())
+        .and(accounts_filter.clone())
+        .map(
+            |username: String,
+             options: PlayOptions,
+             query: SessionQuery,
+             w: ws::Ws,
+             ic_tx: ConnTx,
+             accounts: Arc<Accounts>| {
+                let logged_in = query.token.and_then(|token| accounts.session(&token));
+                if logged_in.as_deref() != Some(username.as_str()) {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                w.on_upgrade(move |socket| {
+                    connection::handle_connection(username, options, socket, ic_tx)
+                })
+                .into_response()
+            },
+        );
+
+    let api_register = warp::path!("api" / "register")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(accounts_filter.clone())
+        .and(hashing_filter.clone())
+        .then(
+            |creds: Credentials, accounts: Arc<Accounts>, hashing: Hashing| async move {
+                let username = creds.username.clone();
+                let result = hashing
+                    .run(&username, move || accounts.register(&creds))
+                    .await;
+                session_reply(username, result, StatusCode::CREATED)
+            },
+        );
+
+    let api_login = warp::path!("api" / "login")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(accounts_filter.clone())
+        .and(hashing_filter)
+        .then(
+            |creds: Credentials, accounts: Arc<Accounts>, hashing: Hashing| async move {
+                let username = creds.username.clone();
+                let result = hashing.run(&username, move || accounts.login(&creds)).await;
+                session_reply(username, result, StatusCode::OK)
+            },
+        );
+
+    let ws_watch = warp::path!("watch" / usize)
         .and(warp::ws())
-        .and(ic_filter)
-        .map(|username: String, w: ws::Ws, ic_tx: ConnTx| {
-            w.on_upgrade(move |socket| connection::handle_connection(username, socket, ic_tx))
+        .and(ic_filter.clone())
+        .map(|game_id: usize, w: ws::Ws, ic_tx: ConnTx| {
+            w.on_upgrade(move |socket| connection::handle_spectator(game_id, socket, ic_tx))
+        });
+
+    let ws_lobby = warp::path!("lobby")
+        .and(warp::ws())
+        .and(ic_filter.clone())
+        .map(|w: ws::Ws, ic_tx: ConnTx| {
+            w.on_upgrade(move |socket| connection::handle_observer(socket, ic_tx))
+        });
+
+    let archive = Archive::new(ARCHIVE_DIR);
+    let archive_filter = warp::any().map(move || archive.clone());
+
+    let api_games = warp::path!("api" / "games")
+        .and(warp::get())
+        .and(warp::query::<GamesQuery>())
+        .and(archive_filter.clone())
+        .then(|query: GamesQuery, archive: Archive| async move {
+            match tokio::task::spawn_blocking(move || archive.list(&query)).await {
+                Ok(Ok(records)) => warp::reply::json(&records).into_response(),
+                Ok(Err(e)) => {
+                    println!("[Archive] Failed to list games: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        });
+
+    let api_game = warp::path!("api" / "games" / String)
+        .and(warp::get())
+        .and(archive_filter.clone())
+        .then(|id: String, archive: Archive| async move {
+            let read = id.clone();
+            match tokio::task::spawn_blocking(move || archive.get(&read)).await {
+                Ok(Ok(Some(record))) => warp::reply::json(&record).into_response(),
+                Ok(Ok(None)) => StatusCode::NOT_FOUND.into_response(),
+                Ok(Err(e)) => {
+                    println!("[Archive] Failed to read game \"{}\": {}", id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
         });
 
-    let routes = static_files.or(ws_play);
+    // the lobby owns tournaments, these ask it and wait for the answer
+    let api_create_tournament = warp::path!("api" / "tournaments")
+        .and(warp::post())
+        .and(warp::query::<SessionQuery>())
+        .and(warp::body::json())
+        .and(tournament_filter.clone())
+        .and(accounts_filter.clone())
+        .then(
+            |query: SessionQuery,
+             spec: TournamentSpec,
+             tournament_tx: TournamentTx,
+             accounts: Arc<Accounts>| async move {
+                // only players with an account get to run one
+                if query
+                    .token
+                    .and_then(|token| accounts.session(&token))
+                    .is_none()
+                {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let _ = tournament_tx.send(TournamentRequest::Create(spec, reply_tx));
+                match reply_rx.await {
+                    Ok(Ok(id)) => warp::reply::with_status(
+                        warp::reply::json(&json!({ "id": id })),
+                        StatusCode::CREATED,
+                    )
+                    .into_response(),
+                    Ok(Err(e)) => warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": e.to_string() })),
+                        StatusCode::BAD_REQUEST,
+                    )
+                    .into_response(),
+                    Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                }
+            },
+        );
+
+    let api_tournaments = warp::path!("api" / "tournaments")
+        .and(warp::get())
+        .and(tournament_filter.clone())
+        .then(|tournament_tx: TournamentTx| async move {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            let _ = tournament_tx.send(TournamentRequest::List(reply_tx));
+            match reply_rx.await {
+                Ok(tournaments) => warp::reply::json(&tournaments).into_response(),
+                Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+            }
+        });
+
+    let api_tournament = warp::path!("api" / "tournaments" / usize)
+        .and(warp::get())
+        .and(tournament_filter.clone())
+        .then(|id: usize, tournament_tx: TournamentTx| async move {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            let _ = tournament_tx.send(TournamentRequest::Get(id, reply_tx));
+            match reply_rx.await {
+                Ok(Some(tournament)) => warp::reply::json(&tournament).into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+            }
+        });
+
+    let routes = static_files
+        .or(ws_play)
+        .or(ws_watch)
+        .or(ws_lobby)
+        .or(api_games)
+        .or(api_game)
+        .or(api_create_tournament)
+        .or(api_tournaments)
+        .or(api_tournament)
+        .or(api_register)
+        .or(api_login);
 
     warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
 }
+
+// a new session token, or why there isn't one
+// what a register or login request needs before it gets to hash anything
+struct Hashing {
+    attempts: Arc<Attempts>,
+    slots: Arc<Semaphore>,
+}
+
+impl Hashing {
+    // turned away if the name is being tried too often, otherwise waits
+    // for a free slot and hashes off the async workers
+    async fn run<F>(
+        self,
+        username: &str,
+        f: F,
+    ) -> Result<Result<String, AccountsError>, tokio::task::JoinError>
+    where
+        F: FnOnce() -> Result<String, AccountsError> + Send + 'static,
+    {
+        if !self.attempts.allow(username) {
+            return Ok(Err(AccountsError::TooManyAttempts));
+        }
+        let Ok(permit) = self.slots.acquire_owned().await else {
+            unreachable!(); // the semaphore is never closed
+        };
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f()
+        })
+        .await
+    }
+}
+
+fn session_reply(
+    username: String,
+    result: Result<Result<String, AccountsError>, tokio::task::JoinError>,
+    status: StatusCode,
+) -> warp::reply::Response {
+    match result {
+        Ok(Ok(token)) => warp::reply::with_status(
+            warp::reply::json(&json!({ "username": username, "token": token })),
+            status,
+        )
+        .into_response(),
+        Ok(Err(e)) if e.is_rejection() => {
+            let status = match e {
+                AccountsError::WrongPassword => StatusCode::UNAUTHORIZED,
+                AccountsError::UsernameTaken => StatusCode::CONFLICT,
+                AccountsError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            warp::reply::with_status(
+                warp::reply::json(&json!({ "error": e.to_string() })),
+                status,
+            )
+            .into_response()
+        }
+        Ok(Err(e)) => {
+            println!("[Accounts] Failed for \"{}\": {}", username, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::json_file::{self, JsonFileError, Writer};
+
+mod test;
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+// how far a single game can move a rating
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Debug, Error)]
+pub enum RatingsError {
+    #[error("ratings file {0}")]
+    File(#[from] JsonFileError),
+}
+
+// how a rated game ended
+#[derive(Debug)]
+pub enum Outcome {
+    Won(String),
+    Draw,
+}
+
+// Elo ratings by username, kept in a JSON file between runs
+#[derive(Debug)]
+pub struct Ratings {
+    players: HashMap<String, f64>,
+    writer: Writer<HashMap<String, f64>>,
+}
+
+impl Ratings {
+    // a missing file just means nobody has played yet, needs the runtime
+    // to save in
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, RatingsError> {
+        let path = path.into();
+        let players = json_file::load(&path)?;
+        Ok(Self {
+            players,
+            writer: Writer::spawn(path, "Ratings"),
+        })
+    }
+
+    pub fn rating(&self, username: &str) -> i32 {
+        self.players
+            .get(username)
+            .copied()
+            .unwrap_or(DEFAULT_RATING)
+            .round() as i32
+    }
+
+    // updates both players, the file catches up in the background
+    pub fn record(&mut self, red: &str, blue: &str, outcome: &Outcome) {
+        let red_rating = self.players.get(red).copied().unwrap_or(DEFAULT_RATING);
+        let blue_rating = self.players.get(blue).copied().unwrap_or(DEFAULT_RATING);
+
+        let red_score = match outcome {
+            Outcome::Won(winner) if winner == red => 1.0,
+            Outcome::Won(_) => 0.0,
+            Outcome::Draw => 0.5,
+        };
+        let red_expected = 1.0 / (1.0 + 10f64.powf((blue_rating - red_rating) / 400.0));
+        let change = K_FACTOR * (red_score - red_expected);
+
+        self.players.insert(red.to_string(), red_rating + change);
+        self.players.insert(blue.to_string(), blue_rating - change);
+        self.writer.write(self.players.clone());
+    }
+
+    #[allow(unused)] // used by tests
+    pub async fn flush(&self) {
+        self.writer.flush().await;
+    }
+}
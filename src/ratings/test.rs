@@ -0,0 +1,59 @@
+#[allow(unused_imports)]
+use crate::json_file::TempFile;
+#[allow(unused_imports)]
+use crate::ratings::{DEFAULT_RATING, Outcome, Ratings, RatingsError};
+
+#[tokio::test]
+async fn test_ratings_start_at_default() {
+    let file = TempFile::new("ratings.json");
+    let ratings = Ratings::load(&file.0).unwrap();
+    assert_eq!(ratings.rating("alice"), DEFAULT_RATING as i32);
+}
+
+#[tokio::test]
+async fn test_ratings_record() {
+    let file = TempFile::new("ratings.json");
+    let mut ratings = Ratings::load(&file.0).unwrap();
+    // evenly matched, the winner takes half of what's at stake
+    ratings.record("alice", "bob", &Outcome::Won("alice".to_string()));
+    assert_eq!(
+        (ratings.rating("alice"), ratings.rating("bob")),
+        (1516, 1484)
+    );
+
+    // a draw pulls the two together
+    ratings.record("alice", "bob", &Outcome::Draw);
+    assert_eq!(
+        (ratings.rating("alice"), ratings.rating("bob")),
+        (1515, 1485)
+    );
+
+    // an upset moves more than the 16 an even game would
+    ratings.record("alice", "bob", &Outcome::Won("bob".to_string()));
+    assert_eq!(
+        (ratings.rating("alice"), ratings.rating("bob")),
+        (1497, 1503)
+    );
+
+    // points are only ever moved between the players
+    assert_eq!(ratings.rating("alice") + ratings.rating("bob"), 3000);
+    // nobody else is touched
+    assert_eq!(ratings.rating("carol"), DEFAULT_RATING as i32);
+}
+
+#[tokio::test]
+async fn test_ratings_saved() {
+    let file = TempFile::new("ratings.json");
+    let mut ratings = Ratings::load(&file.0).unwrap();
+    ratings.record("alice", "bob", &Outcome::Won("bob".to_string()));
+    // the file is written in the background
+    ratings.flush().await;
+    let ratings = Ratings::load(&file.0).unwrap();
+    assert_eq!(
+        (ratings.rating("alice"), ratings.rating("bob")),
+        (1484, 1516)
+    );
+
+    std::fs::write(&file.0, "{not json").unwrap();
+    assert!(matches!(Ratings::load(&file.0), Err(RatingsError::File(_))));
+}
@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::accounts;
+use crate::connect4::{Rules, RulesError, Variant};
+use crate::connection::PlayOptions;
+use crate::game::clock::TimeControl;
+use crate::ratings::Outcome;
+
+mod test;
+
+#[derive(Debug, Error)]
+pub enum TournamentError {
+    #[error("a tournament needs at least 2 players")]
+    TooFewPlayers,
+    #[error("\"{0}\" is on the roster twice")]
+    DuplicatePlayer(String),
+    #[error("\"{0}\" can't be a username")]
+    InvalidUsername(String),
+    #[error("too many tournaments are running, try again once one is over")]
+    TooMany,
+    #[error(transparent)]
+    InvalidRules(#[from] RulesError),
+    #[error("games can only be the best of 1, 3, 5 or 7")]
+    InvalidSeries,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    // everyone plays everyone once
+    RoundRobin,
+    // losers are out, draws are replayed
+    Knockout,
+}
+
+// body of POST /api/tournaments, anything /play takes that doesn't apply
+// to a tournament is turned away
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TournamentSpec {
+    pub name: String,
+    pub format: Format,
+    // seeded in this order
+    pub players: Vec<String>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub connect: Option<usize>,
+    pub variant: Option<Variant>,
+    // seconds
+    pub move_time: Option<u32>,
+    pub clock: Option<u32>,
+    pub increment: Option<u32>,
+    pub best_of: Option<u32>,
+}
+
+impl TournamentSpec {
+    // the game settings, defaulted the same way /play defaults them
+    pub fn options(&self) -> PlayOptions {
+        PlayOptions {
+            width: self.width,
+            height: self.height,
+            connect: self.connect,
+            variant: self.variant,
+            move_time: self.move_time,
+            clock: self.clock,
+            increment: self.increment,
+            best_of: self.best_of,
+            ..PlayOptions::default()
+        }
+    }
+}
+
+// sent by the HTTP routes, the lobby answers on the oneshot
+#[derive(Debug)]
+pub enum TournamentRequest {
+    Create(
+        TournamentSpec,
+        oneshot::Sender<Result<usize, TournamentError>>,
+    ),
+    Get(usize, oneshot::Sender<Option<Tournament>>),
+    List(oneshot::Sender<Vec<Tournament>>),
+}
+
+pub type TournamentTx = mpsc::UnboundedSender<TournamentRequest>;
+pub type TournamentRx = mpsc::UnboundedReceiver<TournamentRequest>;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum PairingResult {
+    Won { winner: String },
+    Draw,
+    // nobody to play this round
+    Bye,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Pairing {
+    pub red: String,
+    // None is a bye for red
+    pub blue: Option<String>,
+    pub result: Option<PairingResult>,
+    // the lobby's game id while it's being played, for /watch
+    pub game: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Standing {
+    pub username: String,
+    pub played: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    // a win is 1, a draw half
+    pub points: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Tournament {
+    pub id: usize,
+    pub name: String,
+    pub format: Format,
+    pub players: Vec<String>,
+    pub rules: Rules,
+    pub time_control: TimeControl,
+    pub best_of: u32,
+    // round robins are drawn up front, knockouts one round at a time
+    pub rounds: Vec<Vec<Pairing>>,
+    // index into rounds
+    pub round: usize,
+    pub standings: Vec<Standing>,
+    pub finished: bool,
+    pub winner: Option<String>,
+}
+
+impl Tournament {
+    pub fn new(
+        id: usize,
+        spec: TournamentSpec,
+        rules: Rules,
+        time_control: TimeControl,
+        best_of: u32,
+    ) -> Result<Self, TournamentError> {
+        let players = spec.players;
+        if players.len() < 2 {
+            return Err(TournamentError::TooFewPlayers);
+        }
+        let mut seen = HashSet::new();
+        for player in &players {
+            if !accounts::is_valid_username(player) {
+                return Err(TournamentError::InvalidUsername(player.clone()));
+            }
+            if !seen.insert(player) {
+                return Err(TournamentError::DuplicatePlayer(player.clone()));
+            }
+        }
+
+        let rounds = match spec.format {
+            Format::RoundRobin => round_robin(&players),
+            Format::Knockout => vec![knockout_round(&players)],
+        };
+        let mut tournament = Self {
+            id,
+            name: spec.name,
+            format: spec.format,
+            players,
+            rules,
+            time_control,
+            best_of,
+            rounds,
+            round: 0,
+            standings: Vec::new(),
+            finished: false,
+            winner: None,
+        };
+        tournament.update();
+        Ok(tournament)
+    }
+
+    pub fn is_player(&self, username: &str) -> bool {
+        self.players.iter().any(|p| p == username)
+    }
+
+    // the current round's pairings that are still waiting to be played
+    pub fn pending(&self) -> Vec<usize> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.rounds[self.round]
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.result.is_none() && p.game.is_none())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn pairing(&self, round: usize, index: usize) -> Option<&Pairing> {
+        self.rounds.get(round)?.get(index)
+    }
+
+    pub fn started(&mut self, index: usize, game: usize) {
+        self.rounds[self.round][index].game = Some(game);
+    }
+
+    // None means the game never finished, it'll be played again
+    pub fn record(&mut self, round: usize, index: usize, outcome: Option<&Outcome>) {
+        let Some(pairing) = self.rounds.get_mut(round).and_then(|r| r.get_mut(index)) else {
+            return;
+        };
+        pairing.game = None;
+        pairing.result = match (outcome, self.format) {
+            (Some(Outcome::Won(winner)), _) => Some(PairingResult::Won {
+                winner: winner.clone(),
+            }),
+            (Some(Outcome::Draw), Format::RoundRobin) => Some(PairingResult::Draw),
+            // someone has to go through
+            (Some(Outcome::Draw), Format::Knockout) | (None, _) => None,
+        };
+        self.update();
+    }
+
+    // recounts the standings and moves on once the round is done
+    fn update(&mut self) {
+        self.standings = self.count_standings();
+        while !self.finished && self.rounds[self.round].iter().all(|p| p.result.is_some()) {
+            self.next_round();
+        }
+    }
+
+    fn next_round(&mut self) {
+        match self.format {
+            Format::RoundRobin => {
+                self.round += 1;
+                if self.round == self.rounds.len() {
+                    self.round -= 1;
+                    self.finished = true;
+                    // nobody wins a tie on points
+                    self.winner = match self.standings.as_slice() {
+                        [first, second, ..] if first.points == second.points => None,
+                        [first, ..] => Some(first.username.clone()),
+                        [] => None,
+                    };
+                }
+            }
+            Format::Knockout => {
+                let through: Vec<String> = self.rounds[self.round]
+                    .iter()
+                    .map(|p| match &p.result {
+                        Some(PairingResult::Won { winner }) => winner.clone(),
+                        // a bye
+                        _ => p.red.clone(),
+                    })
+                    .collect();
+                if through.len() == 1 {
+                    self.finished = true;
+                    self.winner = through.into_iter().next();
+                    return;
+                }
+                self.rounds.push(knockout_round(&through));
+                self.round += 1;
+            }
+        }
+    }
+
+    fn count_standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .players
+            .iter()
+            .map(|username| Standing {
+                username: username.clone(),
+                played: 0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+                points: 0.0,
+            })
+            .collect();
+        for pairing in self.rounds.iter().flatten() {
+            let Some(blue) = &pairing.blue else {
+                continue;
+            };
+            for username in [&pairing.red, blue] {
+                let Some(standing) = standings.iter_mut().find(|s| &s.username == username) else {
+                    continue;
+                };
+                match &pairing.result {
+                    Some(PairingResult::Won { winner }) if winner == username => {
+                        standing.wins += 1;
+                        standing.points += 1.0;
+                    }
+                    Some(PairingResult::Won { .. }) => standing.losses += 1,
+                    Some(PairingResult::Draw) => {
+                        standing.draws += 1;
+                        standing.points += 0.5;
+                    }
+                    Some(PairingResult::Bye) | None => continue,
+                }
+                standing.played += 1;
+            }
+        }
+        // stable, so ties stay in seeding order
+        standings.sort_by(|a, b| b.points.total_cmp(&a.points).then(b.wins.cmp(&a.wins)));
+        standings
+    }
+}
+
+// circle method: the first player stays put while the rest rotate around
+// them, colors alternate each round
+fn round_robin(players: &[String]) -> Vec<Vec<Pairing>> {
+    let mut seats: Vec<Option<&String>> = players.iter().map(Some).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+    let n = seats.len();
+    let mut rounds = Vec::new();
+    for round in 0..n - 1 {
+        let pairings = (0..n / 2)
+            .map(|i| {
+                let (a, b) = (seats[i], seats[n - 1 - i]);
+                let (red, blue) = match (a, b, round % 2 == 0) {
+                    (Some(a), b, true) | (b @ None, Some(a), true) => (a, b),
+                    (Some(a), Some(b), false) => (b, Some(a)),
+                    (Some(a), None, false) | (None, Some(a), false) => (a, None),
+                    (None, None, _) => unreachable!(),
+                };
+                new_pairing(red, blue)
+            })
+            .collect();
+        rounds.push(pairings);
+        seats[1..].rotate_right(1);
+    }
+    rounds
+}
+
+// top seed against bottom seed, an odd one out in the middle gets a bye
+fn knockout_round(players: &[String]) -> Vec<Pairing> {
+    let n = players.len();
+    let mut pairings: Vec<Pairing> = (0..n / 2)
+        .map(|i| new_pairing(&players[i], Some(&players[n - 1 - i])))
+        .collect();
+    if n % 2 == 1 {
+        pairings.push(new_pairing(&players[n / 2], None));
+    }
+    pairings
+}
+
+fn new_pairing(red: &str, blue: Option<&String>) -> Pairing {
+    Pairing {
+        red: red.to_string(),
+        blue: blue.cloned(),
+        result: blue.is_none().then_some(PairingResult::Bye),
+        game: None,
+    }
+}
@@ -0,0 +1,211 @@
+#[allow(unused_imports)]
+use crate::connect4::Rules;
+#[allow(unused_imports)]
+use crate::game::clock::TimeControl;
+#[allow(unused_imports)]
+use crate::ratings::Outcome;
+#[allow(unused_imports)]
+use crate::tournament::{Format, PairingResult, Tournament, TournamentError, TournamentSpec};
+
+#[allow(unused)] // used by tests
+fn tournament(format: Format, players: &[&str]) -> Result<Tournament, TournamentError> {
+    let spec = serde_json::from_value::<TournamentSpec>(serde_json::json!({
+        "name": "test",
+        "format": format,
+        "players": players,
+    }))
+    .unwrap();
+    let rules = Rules::new(7, 6, 4).unwrap();
+    Tournament::new(0, spec, rules, TimeControl::default(), 1)
+}
+
+// records the result of the current round's game between a and b
+#[allow(unused)] // used by tests
+fn play(tournament: &mut Tournament, a: &str, b: &str, outcome: Option<Outcome>) {
+    let round = tournament.round;
+    let index = tournament.rounds[round]
+        .iter()
+        .position(|p| {
+            let blue = p.blue.as_deref();
+            (p.red == a && blue == Some(b)) || (p.red == b && blue == Some(a))
+        })
+        .unwrap();
+    tournament.record(round, index, outcome.as_ref());
+}
+
+#[allow(unused)] // used by tests
+fn won(username: &str) -> Option<Outcome> {
+    Some(Outcome::Won(username.to_string()))
+}
+
+#[test]
+fn test_tournament_rejects_rosters() {
+    assert!(matches!(
+        tournament(Format::RoundRobin, &["a"]),
+        Err(TournamentError::TooFewPlayers)
+    ));
+    assert!(matches!(
+        tournament(Format::Knockout, &["a", "b", "a"]),
+        Err(TournamentError::DuplicatePlayer(p)) if p == "a"
+    ));
+    for name in ["", "has space", "<b>bold</b>"] {
+        assert!(matches!(
+            tournament(Format::Knockout, &["a", name]),
+            Err(TournamentError::InvalidUsername(p)) if p == name
+        ));
+    }
+}
+
+#[test]
+fn test_spec_rejects_play_options() {
+    let spec = r#"{"name": "t", "format": "knockout", "players": ["a", "b"], "best_of": 3}"#;
+    let spec = serde_json::from_str::<TournamentSpec>(spec).unwrap();
+    assert_eq!(spec.options().best_of, Some(3));
+
+    for extra in [r#""bot": "easy""#, r#""room": "new""#, r#""tournament": 1"#] {
+        let spec =
+            format!(r#"{{"name": "t", "format": "knockout", "players": ["a", "b"], {extra}}}"#);
+        assert!(serde_json::from_str::<TournamentSpec>(&spec).is_err());
+    }
+}
+
+#[test]
+fn test_round_robin_odd_players() {
+    let players = ["a", "b", "c"];
+    let tournament = tournament(Format::RoundRobin, &players).unwrap();
+    assert_eq!(tournament.rounds.len(), 3);
+
+    for player in players {
+        let byes = tournament
+            .rounds
+            .iter()
+            .flatten()
+            .filter(|p| p.blue.is_none() && p.red == player)
+            .count();
+        assert_eq!(byes, 1, "{player} should sit out once");
+        for other in players.iter().filter(|&&o| o != player) {
+            let games = tournament
+                .rounds
+                .iter()
+                .flatten()
+                .filter(|p| p.red == player && p.blue.as_deref() == Some(*other))
+                .count();
+            let reversed = tournament
+                .rounds
+                .iter()
+                .flatten()
+                .filter(|p| p.red == *other && p.blue.as_deref() == Some(player))
+                .count();
+            assert_eq!(games + reversed, 1, "{player} and {other} meet once");
+        }
+    }
+    // byes are decided from the start, only the real game is left to play
+    for round in &tournament.rounds {
+        let bye = round.iter().find(|p| p.blue.is_none()).unwrap();
+        assert!(matches!(bye.result, Some(PairingResult::Bye)));
+    }
+    assert_eq!(tournament.pending().len(), 1);
+}
+
+#[test]
+fn test_round_robin_standings_tie_breaks() {
+    let mut tournament = tournament(Format::RoundRobin, &["a", "b", "c", "d"]).unwrap();
+    play(&mut tournament, "a", "d", won("a"));
+    play(&mut tournament, "b", "c", Some(Outcome::Draw));
+    assert_eq!(tournament.round, 1);
+    // b and c are level on everything, seeding decides
+    let order: Vec<&str> = tournament
+        .standings
+        .iter()
+        .map(|s| s.username.as_str())
+        .collect();
+    assert_eq!(order, ["a", "b", "c", "d"]);
+
+    play(&mut tournament, "a", "c", Some(Outcome::Draw));
+    play(&mut tournament, "b", "d", won("d"));
+    // c and d both have a point, d got theirs from a win
+    let order: Vec<&str> = tournament
+        .standings
+        .iter()
+        .map(|s| s.username.as_str())
+        .collect();
+    assert_eq!(order, ["a", "d", "c", "b"]);
+    let a = &tournament.standings[0];
+    assert_eq!((a.played, a.wins, a.draws, a.losses), (2, 1, 1, 0));
+    assert_eq!(a.points, 1.5);
+}
+
+#[test]
+fn test_round_robin_completion() {
+    let mut tournament = tournament(Format::RoundRobin, &["a", "b", "c", "d"]).unwrap();
+    play(&mut tournament, "a", "d", won("a"));
+    play(&mut tournament, "b", "c", won("b"));
+    play(&mut tournament, "a", "c", won("a"));
+    play(&mut tournament, "b", "d", won("b"));
+    assert!(!tournament.finished);
+    play(&mut tournament, "c", "d", won("c"));
+    // a game that never finished is played again
+    play(&mut tournament, "a", "b", None);
+    assert!(!tournament.finished);
+    assert_eq!(tournament.pending().len(), 1);
+    play(&mut tournament, "a", "b", won("a"));
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner.as_deref(), Some("a"));
+    assert!(tournament.pending().is_empty());
+}
+
+#[test]
+fn test_round_robin_tie_has_no_winner() {
+    let mut tournament = tournament(Format::RoundRobin, &["a", "b"]).unwrap();
+    play(&mut tournament, "a", "b", Some(Outcome::Draw));
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner, None);
+}
+
+#[test]
+fn test_knockout_bye_draw_and_forfeit() {
+    let mut tournament = tournament(Format::Knockout, &["a", "b", "c"]).unwrap();
+    // top seed plays bottom seed, the middle one goes through
+    let round = &tournament.rounds[0];
+    assert_eq!(round.len(), 2);
+    assert_eq!(
+        (round[0].red.as_str(), round[0].blue.as_deref()),
+        ("a", Some("c"))
+    );
+    assert_eq!(
+        (round[1].red.as_str(), round[1].blue.as_deref()),
+        ("b", None)
+    );
+    assert_eq!(tournament.pending(), [0]);
+
+    // somebody has to go through, draws and unfinished games are replayed
+    tournament.started(0, 7);
+    assert!(tournament.pending().is_empty());
+    play(&mut tournament, "a", "c", Some(Outcome::Draw));
+    assert_eq!(tournament.round, 0);
+    assert_eq!(tournament.pending(), [0]);
+    play(&mut tournament, "a", "c", None);
+    assert_eq!(tournament.pending(), [0]);
+
+    // a forfeit is a win like any other
+    play(&mut tournament, "a", "c", won("c"));
+    assert_eq!(tournament.round, 1);
+    let round = &tournament.rounds[1];
+    assert_eq!(round.len(), 1);
+    assert_eq!(
+        (round[0].red.as_str(), round[0].blue.as_deref()),
+        ("c", Some("b"))
+    );
+    // the bye wasn't a game
+    let b = tournament
+        .standings
+        .iter()
+        .find(|s| s.username == "b")
+        .unwrap();
+    assert_eq!(b.played, 0);
+
+    play(&mut tournament, "b", "c", won("b"));
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner.as_deref(), Some("b"));
+    assert!(tournament.pending().is_empty());
+}
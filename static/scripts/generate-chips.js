@@ -1,44 +1,70 @@
-function init_chips(drop_chip) {
+function init_chips(drop_chip, pop_chip) {
   let board = document.getElementById("board");
   let chips = new Map();
-  for (let r = 5; r >= 0; r--) {
-    for (let c = 0; c < 7; c++) {
-      let chip = document.createElement("div");
-      let id = chip_id(r, c);
-      chip.id = id;
-      chip.className = "chip";
-      chip.setAttribute("col", c);
-
-      chips.set(id, chip);
-      board.appendChild(chip);
+  let width = 0;
+  let height = 0;
+
+  this.resize = function (new_width, new_height) {
+    if (new_width == width && new_height == height) return;
+    width = new_width;
+    height = new_height;
+    chips.clear();
+    board.replaceChildren();
+    board.style.gridTemplateColumns = `repeat(${width}, 1fr)`;
+    board.style.gridTemplateRows = `repeat(${height}, 1fr)`;
+    for (let r = height - 1; r >= 0; r--) {
+      for (let c = 0; c < width; c++) {
+        let chip = document.createElement("div");
+        let id = chip_id(r, c);
+        chip.id = id;
+        chip.className = "chip";
+        chip.setAttribute("col", c);
+        chip.addEventListener("click", function (e) {
+          let col = e.target.getAttribute("col");
+          drop_chip(parseInt(col));
+        });
+        // right click pops the bottom chip in PopOut games
+        chip.addEventListener("contextmenu", function (e) {
+          e.preventDefault();
+          let col = e.target.getAttribute("col");
+          pop_chip(parseInt(col));
+        });
+
+        chips.set(id, chip);
+        board.appendChild(chip);
+      }
     }
-  }
+  };
 
   this.display = function (board_layout) {
-    for (let r = 0; r < 6; r++) {
-      for (let c = 0; c < 7; c++) {
-        let id = chip_id(r, c);
+    this.resize(board_layout.length, board_layout[0].length);
+    for (let r = 0; r < height; r++) {
+      for (let c = 0; c < width; c++) {
+        // pops move chips down and empty cells, so every cell is repainted
+        let chip = chips.get(chip_id(r, c));
         let color = board_layout[c][r];
-        if (color == null) continue;
-        else if (color == "Red") chips.get(id).classList.add("chip-red");
-        else if (color == "Blue") chips.get(id).classList.add("chip-blue");
+        chip.classList.toggle("chip-red", color == "Red");
+        chip.classList.toggle("chip-blue", color == "Blue");
       }
     }
   };
 
+  // marks the cells of the winning line
+  this.highlight = function (cells) {
+    for (const cell of cells) {
+      chips.get(chip_id(cell.row, cell.col)).classList.add("chip-win");
+    }
+  };
+
   this.clear = function () {
     for (const [i, chip] of chips) {
       chip.classList.remove("chip-red");
       chip.classList.remove("chip-blue");
+      chip.classList.remove("chip-win");
     }
   };
 
-  for (const [i, chip] of chips) {
-    chip.addEventListener("click", function (e) {
-      let col = e.target.getAttribute("col");
-      drop_chip(parseInt(col));
-    });
-  }
+  this.resize(7, 6);
 
   return this;
 }
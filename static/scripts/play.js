@@ -1,8 +1,41 @@
 window.onload = function (e) {
   let connect_button = document.getElementById("connect");
   let username_field = document.getElementById("username");
+  let password_field = document.getElementById("password");
+  let register_button = document.getElementById("register");
+  let account_message = document.getElementById("account-message");
+  let opponent_field = document.getElementById("opponent");
+  let rules_field = document.getElementById("rules");
+  let time_field = document.getElementById("time");
+  let series_field = document.getElementById("series");
+  let room_field = document.getElementById("room");
+  let tournament_field = document.getElementById("tournament");
+  let resign_button = document.getElementById("resign");
+  let offer_draw_button = document.getElementById("offer-draw");
+  let accept_draw_button = document.getElementById("accept-draw");
+  let decline_draw_button = document.getElementById("decline-draw");
+  let request_undo_button = document.getElementById("request-undo");
+  let accept_undo_button = document.getElementById("accept-undo");
+  let decline_undo_button = document.getElementById("decline-undo");
+  let rematch_button = document.getElementById("rematch");
+  let waiting_list = document.getElementById("waiting");
+  let challenge_box = document.getElementById("challenge");
+  let challenge_text = document.getElementById("challenge-text");
+  let chat_log = document.getElementById("chat-log");
+  let chat_text = document.getElementById("chat-text");
+  let chat_send = document.getElementById("chat-send");
 
   let socket = null;
+  let your_color = null;
+  let your_username = null;
+  // set while a game runs, so a dropped socket can rejoin it
+  let resume = null;
+  // from /api/login or /api/register, every socket needs its token
+  let session = null;
+  // who last challenged us, while we wait for a match
+  let challenger = null;
+  const RESUME_WINDOW = 30000;
+  const RESUME_RETRY = 2000;
 
   function drop_chip(column) {
     if (socket == null) {
@@ -17,8 +50,87 @@ window.onload = function (e) {
     );
   }
 
+  function send_action(type) {
+    if (socket == null) return;
+    socket.send(JSON.stringify({ type: type }));
+  }
+
+  function show_draw_offer(offered) {
+    accept_draw_button.hidden = !offered;
+    decline_draw_button.hidden = !offered;
+  }
+
+  function show_waiting(users) {
+    waiting_list.replaceChildren();
+    for (const user of users) {
+      let item = document.createElement("li");
+      let rules = `${user.rules.width}x${user.rules.height} connect ${user.rules.connect}`;
+      item.textContent = `${with_rating(user.username, user.rating)} (${rules})`;
+      if (user.username != your_username) {
+        let button = document.createElement("button");
+        button.textContent = "Challenge";
+        button.addEventListener("click", function (e) {
+          if (socket == null) return;
+          socket.send(
+            JSON.stringify({ type: "Challenge", opponent: user.username }),
+          );
+        });
+        item.appendChild(button);
+      }
+      waiting_list.appendChild(item);
+    }
+  }
+
+  function show_challenge(from) {
+    challenger = from;
+    challenge_box.hidden = from == null;
+    if (from != null) challenge_text.textContent = `${from} challenges you`;
+  }
+
+  function answer_challenge(type) {
+    if (socket != null && challenger != null) {
+      socket.send(JSON.stringify({ type: type, from: challenger }));
+    }
+    show_challenge(null);
+  }
+
+  function show_chat(text) {
+    let line = document.createElement("li");
+    line.textContent = text;
+    chat_log.appendChild(line);
+    chat_log.scrollTop = chat_log.scrollHeight;
+  }
+
+  function show_undo_request(requested) {
+    accept_undo_button.hidden = !requested;
+    decline_undo_button.hidden = !requested;
+  }
+
+  function game_running(running) {
+    resign_button.disabled = !running;
+    offer_draw_button.disabled = !running;
+    request_undo_button.disabled = !running;
+    if (!running) {
+      show_draw_offer(false);
+      show_undo_request(false);
+    }
+  }
+
+  function pop_chip(column) {
+    if (socket == null) {
+      console.error("cannot pop chip when socket is closed");
+      return;
+    }
+    socket.send(
+      JSON.stringify({
+        type: "PopChip",
+        column: column,
+      }),
+    );
+  }
+
   let status = init_status();
-  let chips = init_chips(drop_chip);
+  let chips = init_chips(drop_chip, pop_chip);
 
   function handle_message(msg) {
     console.log(`Received message:`);
@@ -26,16 +138,145 @@ window.onload = function (e) {
     if (msg.board != null) {
       chips.display(msg.board);
     }
+    if (msg.type == "WaitingUsers") {
+      show_waiting(msg.users);
+      if (!msg.users.some((user) => user.username == challenger)) {
+        show_challenge(null);
+      }
+    }
+    if (msg.type == "Queued") {
+      let wait = msg.estimated_wait != null ? ` ~${msg.estimated_wait}s` : "";
+      status.text(`#${msg.position}${wait}`);
+    }
+    if (msg.type == "Challenged") {
+      show_challenge(msg.from);
+    }
+    if (msg.type == "ChallengeDeclined") {
+      status.text("Declined");
+    }
+    if (msg.type == "UnknownOpponent") {
+      status.text("Not waiting");
+    }
     if (msg.type == "MatchMade") {
+      show_waiting([]);
+      chat_log.replaceChildren();
+      chat_send.disabled = false;
+      show_challenge(null);
+      // rematches reuse the socket, last game's chips are still up
+      chips.clear();
+      rematch_button.hidden = true;
+      chips.resize(msg.rules.width, msg.rules.height);
       status.matchmade(msg);
+      your_color = msg.your_color;
+      resume = {
+        username: msg.your_username,
+        token: msg.resume_token,
+      };
+      game_running(true);
+    }
+    if (msg.type == "ChatMessage") {
+      show_chat(`${msg.from}: ${msg.text}`);
+    }
+    if (msg.type == "InvalidChat") {
+      show_chat("TooFast" in msg ? "(slow down)" : "(not sent)");
+    }
+    if (msg.type == "OpponentLeft") {
+      status.text("Opp Left");
+    }
+    if (msg.type == "OpponentReturned") {
+      status.text("Opp Back");
+    }
+    if (msg.type == "RoomCreated") {
+      status.text(`Room ${msg.code}`);
+    }
+    if (msg.type == "UnknownRoom") {
+      status.text("No such room");
+    }
+    if (msg.type == "TournamentWaiting") {
+      // tournament games aren't rematched, the next round comes instead
+      rematch_button.hidden = true;
+      status.text(`Round ${msg.round + 1}`);
+    }
+    if (msg.type == "TournamentOver") {
+      rematch_button.hidden = true;
+      status.text(msg.winner ? `${msg.winner} wins!` : "Tied");
+    }
+    if (msg.type == "UnknownTournament") {
+      status.text("Not entered");
+    }
+    if (msg.type == "InvalidResumeToken") {
+      resume = null;
     }
     if (msg.type == "Board") {
       status.turn(msg.turn);
+      status.clock(msg.time, msg.turn);
     }
     if (msg.type == "Moved") {
+      // moving on turns down a standing offer
+      if (msg.last_mover == your_color) show_draw_offer(false);
+      show_undo_request(false);
       status.turn(opposite_color(msg.last_mover));
+      status.clock(msg.time, opposite_color(msg.last_mover));
+    }
+    if (["TimedOut", "Resigned", "Forfeited"].includes(msg.type)) {
+      status.win(msg.winner);
+    }
+    if (msg.type == "DrawOffered" && msg.by != your_color) {
+      show_draw_offer(true);
+    }
+    if (msg.type == "DrawDeclined") {
+      show_draw_offer(false);
+    }
+    if (msg.type == "UndoRequested" && msg.by != your_color) {
+      show_undo_request(true);
+    }
+    if (msg.type == "UndoDeclined") {
+      show_undo_request(false);
+      if (msg.by != your_color) status.text("Undo Refused");
+    }
+    if (msg.type == "Undone") {
+      show_undo_request(false);
+      // the Board snapshot that follows redraws everything
+      chips.clear();
+    }
+    if (msg.type == "SeriesScore") {
+      // the next board starts shortly, keep the score up until then
+      let mine = msg.players.indexOf(your_username);
+      // rematches are offered once the whole series is over
+      rematch_button.hidden = true;
+      status.text(`${msg.wins[mine]} - ${msg.wins[1 - mine]}`);
+    }
+    if (msg.type == "SeriesOver") {
+      if (msg.winner == null) status.text("Series Drawn");
+      else status.text(msg.winner == your_username ? "Series Won!" : "Series Lost");
+      rematch_button.hidden = false;
+    }
+    if (msg.type == "RematchOffered" && msg.by != your_color) {
+      status.text("Rematch?");
+    }
+    if (msg.type == "RematchDeclined") {
+      rematch_button.hidden = true;
+    }
+    if (msg.type == "DrawAgreed") {
+      status.win(null);
+    }
+    if (
+      [
+        "Won",
+        "Stalemate",
+        "TimedOut",
+        "Resigned",
+        "Forfeited",
+        "DrawAgreed",
+      ].includes(msg.type)
+    ) {
+      game_running(false);
+      resume = null;
+      rematch_button.hidden = false;
+      rematch_button.disabled = false;
     }
     if (msg.type == "Won") {
+      chips.highlight(msg.line);
       status.win(msg.winner);
     }
     if (msg.type == "Stalemate") {
@@ -45,16 +286,68 @@ window.onload = function (e) {
 
   function buttons_connect(connected) {
     connect_button.disabled = connected;
+    opponent_field.disabled = connected;
+    rules_field.disabled = connected;
+    time_field.disabled = connected;
+    series_field.disabled = connected;
+    room_field.disabled = connected;
+    tournament_field.disabled = connected;
   }
 
-  function connect(username) {
+  function connect(username, bot, rules, time, series, room, tournament) {
     console.log(`Connecting as ${username}...`);
+    let params = new URLSearchParams(rules);
+    for (const [key, value] of new URLSearchParams(time)) params.set(key, value);
+    if (bot == "challenges") params.set("challenges_only", "true");
+    else if (bot) params.set("bot", bot);
+    if (series) params.set("best_of", series);
+    if (room) params.set("room", room);
+    if (tournament) params.set("tournament", tournament);
+    open_socket(username, params);
+  }
+
+  // resolves once there's a session to play with
+  function log_in(action, username, password) {
+    return fetch(`/api/${action}`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ username: username, password: password }),
+    }).then((response) =>
+      response.json().then((body) => {
+        if (!response.ok) throw new Error(body.error);
+        session = body;
+        password_field.value = "";
+        account_message.textContent = `Logged in as ${session.username}`;
+      }),
+    );
+  }
+
+  function rejoin(until) {
+    if (resume == null || Date.now() > until) {
+      resume = null;
+      status.reset(null);
+      buttons_connect(false);
+      game_running(false);
+      return;
+    }
+    status.text("Reconnecting");
+    let params = new URLSearchParams({ resume: resume.token });
+    open_socket(resume.username, params, until);
+  }
+
+  // until is set while trying to rejoin a game
+  function open_socket(username, params, until) {
     let protocol = window.location.protocol == "https:" ? "wss" : "ws";
+    if (session != null) params.set("token", session.token);
+    let query = params.size > 0 ? `?${params}` : "";
+    let opened = false;
+    your_username = username;
     socket = new WebSocket(
-      `${protocol}://${window.location.host}/play/${username}`,
+      `${protocol}://${window.location.host}/play/${username}${query}`,
     );
 
     socket.onopen = function (e) {
+      opened = true;
       buttons_connect(true);
       chips.clear();
       status.reset(username);
@@ -68,8 +361,23 @@ window.onload = function (e) {
 
     socket.onclose = function (e) {
       console.log("Disconnected");
-      buttons_connect(false);
       socket = null;
+      chat_send.disabled = true;
+      if (resume != null) {
+        let deadline = until != null ? until : Date.now() + RESUME_WINDOW;
+        setTimeout(() => rejoin(deadline), RESUME_RETRY);
+        return;
+      }
+      if (!opened) {
+        // turned away at the door, most likely the session ran out
+        session = null;
+        account_message.textContent = "Please log in";
+      }
+      buttons_connect(false);
+      game_running(false);
+      rematch_button.hidden = true;
+      show_waiting([]);
+      show_challenge(null);
     };
 
     socket.onerror = function (e) {
@@ -77,9 +385,76 @@ window.onload = function (e) {
     };
   }
 
+  resign_button.addEventListener("click", function (e) {
+    send_action("Resign");
+  });
+  offer_draw_button.addEventListener("click", function (e) {
+    send_action("OfferDraw");
+  });
+  accept_draw_button.addEventListener("click", function (e) {
+    send_action("AcceptDraw");
+  });
+  decline_draw_button.addEventListener("click", function (e) {
+    send_action("DeclineDraw");
+  });
+  rematch_button.addEventListener("click", function (e) {
+    send_action("Rematch");
+    rematch_button.disabled = true;
+  });
+  request_undo_button.addEventListener("click", function (e) {
+    send_action("RequestUndo");
+  });
+  accept_undo_button.addEventListener("click", function (e) {
+    send_action("AcceptUndo");
+  });
+  decline_undo_button.addEventListener("click", function (e) {
+    send_action("DeclineUndo");
+  });
+  document
+    .getElementById("accept-challenge")
+    .addEventListener("click", function (e) {
+      answer_challenge("AcceptChallenge");
+    });
+  document
+    .getElementById("decline-challenge")
+    .addEventListener("click", function (e) {
+      answer_challenge("DeclineChallenge");
+    });
+
   connect_button.addEventListener("click", function (e) {
-    let username = username_field.value;
-    connect(username);
+    let username = username_field.value.trim();
+    let start = function () {
+      connect(
+        username,
+        opponent_field.value,
+        rules_field.value,
+        time_field.value,
+        series_field.value,
+        room_field.value.trim(),
+        tournament_field.value,
+      );
+    };
+    if (session != null && session.username == username) {
+      start();
+      return;
+    }
+    log_in("login", username, password_field.value)
+      .then(start)
+      .catch((e) => (account_message.textContent = e.message));
+  });
+
+  register_button.addEventListener("click", function (e) {
+    log_in("register", username_field.value.trim(), password_field.value).catch(
+      (e) => (account_message.textContent = e.message),
+    );
+  });
+
+  document.getElementById("chat-form").addEventListener("submit", function (e) {
+    e.preventDefault();
+    let text = chat_text.value.trim();
+    if (socket == null || text == "") return;
+    socket.send(JSON.stringify({ type: "Chat", text: text }));
+    chat_text.value = "";
   });
 
   document
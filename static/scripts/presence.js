@@ -0,0 +1,50 @@
+// the "who's online" panel, fed by /lobby
+window.addEventListener("load", function (e) {
+  let waiting_list = document.getElementById("online-waiting");
+  let games_list = document.getElementById("online-games");
+  let results_list = document.getElementById("online-results");
+  const RETRY = 5000;
+
+  function fill(list, items, describe) {
+    list.replaceChildren();
+    for (const item of items) {
+      let line = document.createElement("li");
+      describe(line, item);
+      list.appendChild(line);
+    }
+  }
+
+  function show(msg) {
+    fill(waiting_list, msg.waiting, function (line, user) {
+      line.textContent = `${user.username} (${user.rating})`;
+    });
+    fill(games_list, msg.games, function (line, game) {
+      let link = document.createElement("a");
+      link.href = `watch.html?game=${game.id}`;
+      link.textContent = `${game.red} vs ${game.blue}`;
+      line.appendChild(link);
+      if (game.best_of > 1) {
+        line.appendChild(document.createTextNode(` (best of ${game.best_of})`));
+      }
+    });
+    fill(results_list, msg.results, function (line, result) {
+      let outcome = result.winner ? `${result.winner} won` : "draw";
+      line.textContent = `${result.red} vs ${result.blue}, ${outcome}`;
+    });
+  }
+
+  function open_feed() {
+    let protocol = window.location.protocol == "https:" ? "wss" : "ws";
+    let socket = new WebSocket(`${protocol}://${window.location.host}/lobby`);
+    socket.onmessage = function (e) {
+      let msg = JSON.parse(e.data);
+      if (msg.type == "LobbyStatus") show(msg);
+    };
+    // the server restarted or the network blipped, try again
+    socket.onclose = function (e) {
+      setTimeout(open_feed, RETRY);
+    };
+  }
+
+  open_feed();
+});
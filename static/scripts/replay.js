@@ -0,0 +1,145 @@
+// steps through archived games, open as replay.html?game={id}
+window.onload = function (e) {
+  let red_name = document.getElementById("red-name");
+  let blue_name = document.getElementById("blue-name");
+  let status = document.getElementById("status");
+  let games_list = document.getElementById("games");
+  let user_field = document.getElementById("user");
+  let more_button = document.getElementById("more");
+
+  // replays can't be played, clicks go nowhere
+  let chips = init_chips(
+    function (column) {},
+    function (column) {},
+  );
+
+  let record = null;
+  // how many moves are on the board
+  let ply = 0;
+  // the search being listed and how many of its games are shown
+  let searched = "";
+  let listed = 0;
+
+  // replays the first ply moves onto an empty board
+  function layout_at(ply) {
+    let board = [];
+    for (let c = 0; c < record.rules.width; c++) {
+      board.push(new Array(record.rules.height).fill(null));
+    }
+    for (const move of record.moves.slice(0, ply)) {
+      let column = board[move.col];
+      if (move.kind == "Pop") {
+        // everything above the popped chip slides down
+        column.shift();
+        column.push(null);
+      } else {
+        column[move.row] = move.color;
+      }
+    }
+    return board;
+  }
+
+  function result_text(result) {
+    switch (result.type) {
+      case "Won":
+        return `${result.winner} won!`;
+      case "TimedOut":
+        return `${result.winner} won on time!`;
+      case "Resigned":
+        return `${result.winner} won by resignation!`;
+      case "Forfeited":
+        return `${result.winner} won by forfeit!`;
+      case "Stalemate":
+      case "DrawAgreed":
+        return "Draw!";
+      default:
+        return "Abandoned";
+    }
+  }
+
+  function show(new_ply) {
+    if (record == null) return;
+    ply = Math.max(0, Math.min(new_ply, record.moves.length));
+    chips.clear();
+    chips.display(layout_at(ply));
+    if (ply == record.moves.length) {
+      status.textContent = result_text(record.result);
+    } else {
+      status.textContent = `Move ${ply}/${record.moves.length}`;
+    }
+  }
+
+  function load_game(id) {
+    fetch(`/api/games/${encodeURIComponent(id)}`)
+      .then((response) => {
+        if (!response.ok) throw new Error(response.status);
+        return response.json();
+      })
+      .then((game) => {
+        record = game;
+        chips.resize(record.rules.width, record.rules.height);
+        red_name.textContent = record.red;
+        blue_name.textContent = record.blue;
+        show(0);
+      })
+      .catch((e) => {
+        console.error(e);
+        status.textContent = "No such game";
+      });
+  }
+
+  // the server hands games out a page at a time, newest first
+  const PAGE_SIZE = 50;
+
+  function search(user, offset) {
+    let params = { offset: offset, limit: PAGE_SIZE };
+    if (user) params.user = user;
+    fetch(`/api/games?${new URLSearchParams(params)}`)
+      .then((response) => response.json())
+      .then((games) => {
+        if (offset == 0) games_list.replaceChildren();
+        searched = user;
+        listed = offset + games.length;
+        more_button.hidden = games.length < PAGE_SIZE;
+        for (const game of games) {
+          let item = document.createElement("li");
+          let ended = new Date(game.ended_at * 1000).toLocaleString();
+          item.textContent = `${game.red} vs ${game.blue}, ${result_text(game.result)} (${ended})`;
+          let button = document.createElement("button");
+          button.textContent = "Replay";
+          button.addEventListener("click", function (e) {
+            load_game(game.id);
+          });
+          item.appendChild(button);
+          games_list.appendChild(item);
+        }
+      })
+      .catch((e) => console.error(e));
+  }
+
+  document.getElementById("first").addEventListener("click", function (e) {
+    show(0);
+  });
+  document.getElementById("previous").addEventListener("click", function (e) {
+    show(ply - 1);
+  });
+  document.getElementById("next").addEventListener("click", function (e) {
+    show(ply + 1);
+  });
+  document.getElementById("last").addEventListener("click", function (e) {
+    if (record != null) show(record.moves.length);
+  });
+  document
+    .getElementById("search-form")
+    .addEventListener("submit", function (e) {
+      e.preventDefault();
+      search(user_field.value.trim(), 0);
+    });
+  more_button.addEventListener("click", function (e) {
+    search(searched, listed);
+  });
+
+  let game = new URLSearchParams(window.location.search).get("game");
+  if (game != null) load_game(game);
+  search("", 0);
+};
@@ -17,18 +17,54 @@ function init_status() {
   let your_name = document.getElementById("your-name");
   let opp_name = document.getElementById("opp-name");
 
+  let your_clock = document.getElementById("your-clock");
+  let opp_clock = document.getElementById("opp-clock");
+
   let status = document.getElementById("status");
 
+  // milliseconds left per color, counted down locally between messages
+  let time_left = null;
+  let ticking = null;
+  let ticker = null;
+
   this.reset = function (username) {
     your_color = null;
     opp_color = null;
-    your_name.innerHTML = username != null ? username : "???";
+    your_name.textContent = username != null ? username : "???";
     opp_name.innerHTML = "???";
     status.innerHTML = username != null ? "Matchmaking" : "Disconnected";
     your_status.style.backgroundColor = c_default;
     opp_status.style.backgroundColor = c_default;
+    this.stop_clock();
+    time_left = null;
+    show_clocks();
+  };
+
+  this.clock = function (time, turn) {
+    this.stop_clock();
+    if (time == null) return;
+    time_left = { Red: time.red, Blue: time.blue };
+    ticking = turn;
+    let last = Date.now();
+    ticker = setInterval(function () {
+      let now = Date.now();
+      time_left[ticking] = Math.max(0, time_left[ticking] - (now - last));
+      last = now;
+      show_clocks();
+    }, 100);
+    show_clocks();
   };
 
+  this.stop_clock = function () {
+    if (ticker != null) clearInterval(ticker);
+    ticker = null;
+  };
+
+  function show_clocks() {
+    your_clock.innerHTML = format_time(time_left, your_color);
+    opp_clock.innerHTML = format_time(time_left, opp_color);
+  }
+
   this.turn = function (color) {
     your_status.style.backgroundColor = convert_color(
       your_color,
@@ -44,15 +80,23 @@ function init_status() {
   this.matchmade = function (msg) {
     your_color = msg.your_color;
     opp_color = opposite_color(your_color);
-    your_name.innerHTML = msg.your_username;
-    opp_name.innerHTML = msg.opponent_username;
+    your_name.textContent = with_rating(msg.your_username, msg.your_rating);
+    opp_name.textContent = with_rating(
+      msg.opponent_username,
+      msg.opponent_rating,
+    );
     status.innerHTML = "Match Made!";
 
     your_status.style.backgroundColor = convert_color(your_color, false);
     opp_status.style.backgroundColor = convert_color(opp_color, false);
   };
 
+  this.text = function (text) {
+    status.textContent = text;
+  };
+
   this.win = function (winner) {
+    this.stop_clock();
     if (winner == null) {
       your_status.style.backgroundColor = convert_color(your_color, false);
       opp_status.style.backgroundColor = convert_color(opp_color, false);
@@ -88,3 +132,15 @@ function opposite_color(color) {
     return "Blue";
   } else return "Red";
 }
+
+function format_time(time_left, color) {
+  if (time_left == null || color == null) return "";
+  let seconds = Math.ceil(time_left[color] / 1000);
+  let minutes = Math.floor(seconds / 60);
+  seconds = seconds % 60;
+  return `${minutes}:${seconds.toString().padStart(2, "0")}`;
+}
+
+function with_rating(username, rating) {
+  return rating != null ? `${username} (${rating})` : username;
+}
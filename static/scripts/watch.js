@@ -0,0 +1,73 @@
+// read-only view of a running game, open as watch.html?game={id}
+window.onload = function (e) {
+  let red_name = document.getElementById("red-name");
+  let blue_name = document.getElementById("blue-name");
+  let status = document.getElementById("status");
+  let chat_log = document.getElementById("chat-log");
+
+  // spectators can't play, clicks go nowhere
+  let chips = init_chips(
+    function (column) {},
+    function (column) {},
+  );
+
+  function handle_message(msg) {
+    if (msg.board != null) {
+      chips.display(msg.board);
+    }
+    if (msg.type == "Spectating") {
+      chips.resize(msg.rules.width, msg.rules.height);
+      red_name.textContent = msg.red_username;
+      blue_name.textContent = msg.blue_username;
+    }
+    if (msg.type == "Board") {
+      status.textContent = `${msg.turn} to move`;
+    }
+    if (msg.type == "Moved") {
+      status.textContent = `${opposite_color(msg.last_mover)} to move`;
+    }
+    if (msg.type == "Undone") {
+      chips.clear();
+    }
+    if (msg.type == "Won") {
+      chips.highlight(msg.line);
+      status.textContent = `${msg.winner} won!`;
+    }
+    if (msg.type == "TimedOut") {
+      status.textContent = `${msg.winner} won on time!`;
+    }
+    if (msg.type == "Resigned") {
+      status.textContent = `${msg.winner} won by resignation!`;
+    }
+    if (msg.type == "Forfeited") {
+      status.textContent = `${msg.winner} won by forfeit!`;
+    }
+    if (msg.type == "Stalemate" || msg.type == "DrawAgreed") {
+      status.textContent = "Draw!";
+    }
+    if (msg.type == "ChatMessage") {
+      let line = document.createElement("li");
+      line.textContent = `${msg.from}: ${msg.text}`;
+      chat_log.appendChild(line);
+      chat_log.scrollTop = chat_log.scrollHeight;
+    }
+    if (msg.type == "UnknownGame") {
+      status.textContent = "No such game";
+    }
+  }
+
+  let game = new URLSearchParams(window.location.search).get("game");
+  if (game == null) {
+    status.textContent = "No game picked";
+    return;
+  }
+  let protocol = window.location.protocol == "https:" ? "wss" : "ws";
+  let socket = new WebSocket(`${protocol}://${window.location.host}/watch/${game}`);
+  socket.onmessage = function (e) {
+    handle_message(JSON.parse(e.data));
+  };
+};
+
+function opposite_color(color) {
+  return color == "Red" ? "Blue" : "Red";
+}